@@ -0,0 +1,206 @@
+//! Output throughput with several busy sessions while the chat polls context.
+//!
+//! Eight writer threads feed coloured build-log output into their own
+//! session, one read-sized chunk at a time, the way PTY reader threads do.
+//! A ninth thread keeps asking for the last 200 lines of each session, like
+//! `get_terminal_context`. The same load runs against one map behind a single
+//! tokio mutex (the old layout) and against `SnapshotStore`.
+//!
+//! Run with `cargo bench --bench snapshot_throughput`.
+
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    hint::black_box,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use termalime_lib::{
+    pty::PtySize,
+    scrollback::ScrollbackConfig,
+    snapshot::{SnapshotHandle, SnapshotStore, TerminalSnapshot},
+};
+
+const SESSIONS: usize = 8;
+const BYTES_PER_SESSION: usize = 16 * 1024 * 1024;
+const CHUNK_BYTES: usize = 4096;
+const CONTEXT_LINES: usize = 200;
+
+trait Store: Sync {
+    fn append(&self, session: usize, chunk: &[u8]);
+    fn context(&self, session: usize) -> usize;
+}
+
+/// Every session in one map behind one lock, as `AppState` used to hold them.
+struct GlobalLock {
+    ids: Vec<String>,
+    snapshots: tokio::sync::Mutex<HashMap<String, TerminalSnapshot>>,
+}
+
+impl Store for GlobalLock {
+    fn append(&self, session: usize, chunk: &[u8]) {
+        let mut snapshots = self.snapshots.blocking_lock();
+        if let Some(snapshot) = snapshots.get_mut(&self.ids[session]) {
+            snapshot.append(chunk);
+        }
+    }
+
+    fn context(&self, session: usize) -> usize {
+        let snapshots = self.snapshots.blocking_lock();
+        snapshots
+            .get(&self.ids[session])
+            .and_then(|snapshot| snapshot.page(0, CONTEXT_LINES).ok())
+            .map_or(0, |(lines, _)| lines.len())
+    }
+}
+
+/// Readers keep their session's handle; queries look it up in the store.
+struct PerSession {
+    ids: Vec<String>,
+    store: SnapshotStore,
+    handles: Vec<SnapshotHandle>,
+}
+
+impl Store for PerSession {
+    fn append(&self, session: usize, chunk: &[u8]) {
+        self.handles[session].lock().append(chunk);
+    }
+
+    fn context(&self, session: usize) -> usize {
+        self.store
+            .get(&self.ids[session])
+            .and_then(|snapshot| snapshot.lock().page(0, CONTEXT_LINES).ok())
+            .map_or(0, |(lines, _)| lines.len())
+    }
+}
+
+fn new_snapshot() -> TerminalSnapshot {
+    let size = PtySize {
+        cols: 120,
+        rows: 40,
+        ..PtySize::default()
+    };
+    TerminalSnapshot::new(size, &ScrollbackConfig::default(), None)
+}
+
+fn session_ids() -> Vec<String> {
+    (0..SESSIONS)
+        .map(|index| format!("session-{index}"))
+        .collect()
+}
+
+/// Compiler-style output with SGR colours, long enough to wrap now and then.
+fn noisy_output(session: usize) -> Vec<u8> {
+    let mut text = String::with_capacity(BYTES_PER_SESSION + 256);
+    let mut line = 0_usize;
+    while text.len() < BYTES_PER_SESSION {
+        let _ = write!(
+            text,
+            "\x1b[2m[{:>6}.{:03}]\x1b[0m \x1b[1;32m   Compiling\x1b[0m crate-{session}-{line} v0.{}.{} (/work/session-{session}/crates/crate-{line})",
+            line / 1000,
+            line % 1000,
+            line % 7,
+            line % 13,
+        );
+        if line.is_multiple_of(5) {
+            let _ = write!(
+                text,
+                " \x1b[1;33mwarning\x1b[0m: unused variable `value_{line}` in \x1b[36msrc/module_{}.rs\x1b[0m:{}:{}",
+                line % 17,
+                line % 400,
+                line % 80,
+            );
+        }
+        text.push_str("\r\n");
+        line += 1;
+    }
+    text.truncate(BYTES_PER_SESSION);
+    text.into_bytes()
+}
+
+struct Run {
+    elapsed: Duration,
+    context_reads: usize,
+}
+
+fn run(store: &dyn Store, outputs: &[Vec<u8>]) -> Run {
+    let writers_done = AtomicBool::new(false);
+    let context_reads = AtomicUsize::new(0);
+    let started = Instant::now();
+
+    thread::scope(|scope| {
+        scope.spawn(|| {
+            let mut session = 0;
+            while !writers_done.load(Ordering::Relaxed) {
+                black_box(store.context(session));
+                context_reads.fetch_add(1, Ordering::Relaxed);
+                session = (session + 1) % SESSIONS;
+            }
+        });
+
+        let writers: Vec<_> = outputs
+            .iter()
+            .enumerate()
+            .map(|(session, output)| {
+                scope.spawn(move || {
+                    for chunk in output.chunks(CHUNK_BYTES) {
+                        store.append(session, chunk);
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().expect("writer thread panicked");
+        }
+        writers_done.store(true, Ordering::Relaxed);
+    });
+
+    Run {
+        elapsed: started.elapsed(),
+        context_reads: context_reads.into_inner(),
+    }
+}
+
+fn report(name: &str, run: &Run) {
+    let secs = run.elapsed.as_secs_f64();
+    let mib = (SESSIONS * BYTES_PER_SESSION) as f64 / (1024.0 * 1024.0);
+    println!(
+        "{name:<12} {mib:>6.0} MiB in {secs:>6.2}s  {:>8.1} MiB/s  {:>7} context reads ({:.0}/s)",
+        mib / secs,
+        run.context_reads,
+        run.context_reads as f64 / secs,
+    );
+}
+
+fn main() {
+    let outputs: Vec<Vec<u8>> = (0..SESSIONS).map(noisy_output).collect();
+    println!(
+        "{SESSIONS} sessions x {} MiB in {CHUNK_BYTES}-byte chunks, one context reader",
+        BYTES_PER_SESSION / (1024 * 1024)
+    );
+
+    let ids = session_ids();
+    let global = GlobalLock {
+        snapshots: tokio::sync::Mutex::new(
+            ids.iter().map(|id| (id.clone(), new_snapshot())).collect(),
+        ),
+        ids,
+    };
+    report("global lock", &run(&global, &outputs));
+    drop(global);
+
+    let ids = session_ids();
+    let store = SnapshotStore::default();
+    let handles = ids
+        .iter()
+        .map(|id| store.insert(id, new_snapshot()))
+        .collect();
+    let per_session = PerSession {
+        ids,
+        store,
+        handles,
+    };
+    report("per session", &run(&per_session, &outputs));
+}
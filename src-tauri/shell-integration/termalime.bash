@@ -0,0 +1,79 @@
+# Termalime shell integration for bash.
+# Emits OSC 133 prompt/command marks and OSC 7 working-directory reports.
+
+[[ $- == *i* ]] || return
+[[ -n "${__termalime_loaded-}" ]] && return
+__termalime_loaded=1
+__termalime_ready=0
+__termalime_ran=0
+
+__termalime_preexec() {
+  [[ "$__termalime_ready" == 1 ]] || return
+  [[ -n "${COMP_LINE-}" ]] && return
+  __termalime_ready=0
+  __termalime_ran=1
+
+  local cmd
+  cmd=$(HISTTIMEFORMAT= builtin history 1)
+  if [[ "$cmd" =~ ^\ *[0-9]+\*?\ +(.*)$ ]]; then
+    cmd=${BASH_REMATCH[1]}
+  else
+    cmd=$BASH_COMMAND
+  fi
+  printf '\e]133;C;cmdline=%s\a' "${cmd//[$'\a\e']/}"
+}
+
+__termalime_precmd() {
+  local ret=$?
+  if [[ "$__termalime_ran" == 1 ]]; then
+    printf '\e]133;D;%s\a' "$ret"
+  fi
+  __termalime_ran=0
+  printf '\e]7;file://%s%s\a' "${HOSTNAME-}" "$PWD"
+  printf '\e]133;A\a'
+  return $ret
+}
+
+# bash-preexec owns the DEBUG trap and PROMPT_COMMAND when it is loaded, so
+# hook into it instead of replacing them.
+if [[ -n "${bash_preexec_imported-}${__bp_imported-}" ]]; then
+  __termalime_bp_preexec() {
+    __termalime_ran=1
+    printf '\e]133;C;cmdline=%s\a' "${1//[$'\a\e']/}"
+  }
+  preexec_functions+=(__termalime_bp_preexec)
+  precmd_functions+=(__termalime_precmd)
+else
+  __termalime_status() {
+    return "$1"
+  }
+
+  __termalime_debug() {
+    local ret=$?
+    __termalime_preexec
+    if [[ -n "${__termalime_prev_debug-}" ]]; then
+      __termalime_status "$ret"
+      eval "$__termalime_prev_debug"
+    fi
+  }
+
+  # Keeps any DEBUG trap the user already set, running it after ours with
+  # the same $?. `trap -p` reads empty while this file is being sourced, so
+  # $1 is captured from the first prompt.
+  __termalime_install_debug() {
+    local prev=$1
+    if [[ -n "$prev" ]]; then
+      prev=${prev#"trap -- "}
+      prev=${prev%" DEBUG"}
+      eval "__termalime_prev_debug=$prev"
+    fi
+    trap '__termalime_debug' DEBUG
+    __termalime_installed=1
+  }
+
+  # Newlines rather than ';' so an existing value ending in ';' stays valid.
+  PROMPT_COMMAND="__termalime_precmd"$'\n'"${PROMPT_COMMAND-}"$'\n'
+  PROMPT_COMMAND+='[[ -n "${__termalime_installed-}" ]] || __termalime_install_debug "$(trap -p DEBUG)"'
+  PROMPT_COMMAND+=$'\n'"__termalime_ready=1"
+fi
+PS1="${PS1}\[\e]133;B\a\]"
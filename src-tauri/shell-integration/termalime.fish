@@ -0,0 +1,25 @@
+# Termalime shell integration for fish.
+# Emits OSC 133 prompt/command marks and OSC 7 working-directory reports.
+
+status is-interactive; or exit
+set -q __termalime_loaded; and exit
+set -g __termalime_loaded 1
+
+function __termalime_preexec --on-event fish_preexec
+    printf '\e]133;C;cmdline=%s\a' (string replace -ra '[\a\e]' '' -- $argv[1])
+end
+
+function __termalime_postexec --on-event fish_postexec
+    printf '\e]133;D;%s\a' $status
+end
+
+function __termalime_prompt --on-event fish_prompt
+    printf '\e]7;file://%s%s\a' $hostname $PWD
+    printf '\e]133;A\a'
+end
+
+functions -c fish_prompt __termalime_fish_prompt
+function fish_prompt
+    __termalime_fish_prompt
+    printf '\e]133;B\a'
+end
@@ -0,0 +1,332 @@
+use std::{
+    fs,
+    io::{BufWriter, Write},
+    path::Path,
+    sync::{Mutex, MutexGuard},
+};
+
+use anyhow::{Context, Result};
+use once_cell::sync::OnceCell;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    conversation::{self, ChatMessage, ChatRole, ConversationSummary},
+    unix_millis,
+};
+
+/// File name of the chat database inside the app data directory.
+pub const CHAT_HISTORY_FILE: &str = "chat_history.sqlite3";
+
+/// Process-wide chat log, initialised once the app data dir is known.
+pub static CHAT_HISTORY: OnceCell<ChatHistoryStore> = OnceCell::new();
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS chat_messages (
+    id INTEGER PRIMARY KEY,
+    conversation_id TEXT NOT NULL,
+    role TEXT NOT NULL,
+    content TEXT NOT NULL,
+    model TEXT,
+    persona TEXT,
+    terminal_context TEXT,
+    created_at INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS chat_messages_conversation
+    ON chat_messages (conversation_id, id);
+CREATE VIRTUAL TABLE IF NOT EXISTS chat_messages_fts
+    USING fts5(content, content = 'chat_messages', content_rowid = 'id');
+CREATE TRIGGER IF NOT EXISTS chat_messages_ai AFTER INSERT ON chat_messages BEGIN
+    INSERT INTO chat_messages_fts (rowid, content) VALUES (new.id, new.content);
+END;
+CREATE TRIGGER IF NOT EXISTS chat_messages_ad AFTER DELETE ON chat_messages BEGIN
+    INSERT INTO chat_messages_fts (chat_messages_fts, rowid, content)
+        VALUES ('delete', old.id, old.content);
+END;
+";
+
+/// Selected from `chat_messages` aliased as `m`.
+const MESSAGE_COLUMNS: &str = "m.id, m.conversation_id, m.role, m.content, m.model, m.persona, \
+     m.terminal_context, m.created_at";
+
+/// A message as it is about to be stored.
+pub struct NewChatMessage {
+    pub conversation_id: String,
+    pub role: ChatRole,
+    pub content: String,
+    pub model: Option<String>,
+    pub persona: Option<String>,
+    pub terminal_context: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StoredChatMessage {
+    pub id: i64,
+    pub conversation_id: String,
+    pub role: ChatRole,
+    pub content: String,
+    pub model: Option<String>,
+    pub persona: Option<String>,
+    pub terminal_context: Option<String>,
+    /// Milliseconds since the Unix epoch.
+    pub created_at: u64,
+}
+
+impl StoredChatMessage {
+    fn from_row(row: &Row<'_>) -> rusqlite::Result<Self> {
+        let role: String = row.get(2)?;
+        Ok(Self {
+            id: row.get(0)?,
+            conversation_id: row.get(1)?,
+            role: ChatRole::parse(&role).unwrap_or(ChatRole::User),
+            content: row.get(3)?,
+            model: row.get(4)?,
+            persona: row.get(5)?,
+            terminal_context: row.get(6)?,
+            created_at: row.get::<_, i64>(7)? as u64,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChatSearchHit {
+    #[serde(flatten)]
+    pub message: StoredChatMessage,
+    /// Excerpt around the match with matching terms wrapped in `**`.
+    pub snippet: String,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatExportFormat {
+    Json,
+    Markdown,
+}
+
+/// SQLite log of every prompt and reply, with a full-text index over the
+/// message content.
+pub struct ChatHistoryStore {
+    conn: Mutex<Connection>,
+}
+
+impl ChatHistoryStore {
+    pub fn open(data_dir: &Path) -> Result<Self> {
+        fs::create_dir_all(data_dir)
+            .with_context(|| format!("failed to create {}", data_dir.display()))?;
+        let path = data_dir.join(CHAT_HISTORY_FILE);
+        let conn = Connection::open(&path)
+            .with_context(|| format!("failed to open {}", path.display()))?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.execute_batch(SCHEMA)
+            .context("failed to create chat history tables")?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Stores a prompt and its reply together, so a prompt that never got an
+    /// answer is not left behind on its own.
+    pub fn record_turn(&self, prompt: &NewChatMessage, reply: &NewChatMessage) -> Result<()> {
+        let mut conn = self.lock();
+        let tx = conn.transaction()?;
+        for message in [prompt, reply] {
+            tx.execute(
+                "INSERT INTO chat_messages
+                     (conversation_id, role, content, model, persona, terminal_context, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    message.conversation_id,
+                    message.role.as_str(),
+                    message.content,
+                    message.model,
+                    message.persona,
+                    message.terminal_context,
+                    unix_millis() as i64,
+                ],
+            )
+            .context("failed to record chat message")?;
+        }
+        tx.commit().context("failed to record chat turn")
+    }
+
+    /// Messages of one conversation, oldest first.
+    pub fn conversation(&self, conversation_id: &str) -> Result<Vec<StoredChatMessage>> {
+        let conn = self.lock();
+        let mut statement = conn.prepare(&format!(
+            "SELECT {MESSAGE_COLUMNS} FROM chat_messages m
+             WHERE m.conversation_id = ?1 ORDER BY m.id"
+        ))?;
+        let messages = statement
+            .query_map([conversation_id], StoredChatMessage::from_row)?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(messages)
+    }
+
+    /// Every saved conversation, most recently active first.
+    pub fn conversations(&self) -> Result<Vec<ConversationSummary>> {
+        let conn = self.lock();
+        let mut statement = conn.prepare(
+            "SELECT m.conversation_id, MIN(m.created_at), MAX(m.created_at), COUNT(*),
+                    (SELECT u.content FROM chat_messages u
+                     WHERE u.conversation_id = m.conversation_id AND u.role = 'user'
+                     ORDER BY u.id LIMIT 1),
+                    (SELECT a.model FROM chat_messages a
+                     WHERE a.conversation_id = m.conversation_id AND a.model IS NOT NULL
+                     ORDER BY a.id DESC LIMIT 1)
+             FROM chat_messages m
+             GROUP BY m.conversation_id
+             ORDER BY MAX(m.created_at) DESC",
+        )?;
+        let summaries = statement
+            .query_map([], |row| {
+                let prompt: Option<String> = row.get(4)?;
+                Ok(ConversationSummary {
+                    id: row.get(0)?,
+                    title: prompt
+                        .as_deref()
+                        .map(conversation::title_from)
+                        .unwrap_or_default(),
+                    model: row.get(5)?,
+                    created_at: row.get::<_, i64>(1)? as u64,
+                    updated_at: row.get::<_, i64>(2)? as u64,
+                    message_count: row.get::<_, i64>(3)? as usize,
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(summaries)
+    }
+
+    /// Best matches for `query`, each word of which must appear in the
+    /// message. Words are matched literally, not as FTS5 syntax.
+    pub fn search(&self, query: &str, limit: usize) -> Result<Vec<ChatSearchHit>> {
+        let terms: Vec<String> = query
+            .split_whitespace()
+            .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+            .collect();
+        if terms.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let conn = self.lock();
+        let mut statement = conn.prepare(&format!(
+            "SELECT {MESSAGE_COLUMNS},
+                    snippet(chat_messages_fts, 0, '**', '**', '…', 16)
+             FROM chat_messages_fts
+             JOIN chat_messages m ON m.id = chat_messages_fts.rowid
+             WHERE chat_messages_fts MATCH ?1
+             ORDER BY rank
+             LIMIT ?2"
+        ))?;
+        let hits = statement
+            .query_map(params![terms.join(" "), limit as i64], |row| {
+                Ok(ChatSearchHit {
+                    message: StoredChatMessage::from_row(row)?,
+                    snippet: row.get(8)?,
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(hits)
+    }
+
+    /// Deletes a conversation, returning how many messages it had.
+    pub fn delete_conversation(&self, conversation_id: &str) -> Result<usize> {
+        Ok(self.lock().execute(
+            "DELETE FROM chat_messages WHERE conversation_id = ?1",
+            [conversation_id],
+        )?)
+    }
+
+    /// Deletes a single message, returning its conversation if it existed.
+    pub fn delete_message(&self, id: i64) -> Result<Option<String>> {
+        let conn = self.lock();
+        let conversation_id = conn
+            .query_row(
+                "SELECT conversation_id FROM chat_messages WHERE id = ?1",
+                [id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        conn.execute("DELETE FROM chat_messages WHERE id = ?1", [id])?;
+        Ok(conversation_id)
+    }
+
+    pub fn delete_all(&self) -> Result<usize> {
+        Ok(self.lock().execute("DELETE FROM chat_messages", [])?)
+    }
+
+    /// Writes one conversation, or everything when `conversation_id` is
+    /// `None`, to `path`. Returns the number of messages written.
+    pub fn export(
+        &self,
+        path: &Path,
+        format: ChatExportFormat,
+        conversation_id: Option<&str>,
+    ) -> Result<usize> {
+        let messages: Vec<StoredChatMessage> = match conversation_id {
+            Some(id) => self.conversation(id)?,
+            None => {
+                let conn = self.lock();
+                let mut statement = conn.prepare(&format!(
+                    "SELECT {MESSAGE_COLUMNS} FROM chat_messages m ORDER BY m.conversation_id, m.id"
+                ))?;
+                let messages = statement
+                    .query_map([], StoredChatMessage::from_row)?
+                    .collect::<rusqlite::Result<_>>()?;
+                messages
+            }
+        };
+
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            fs::create_dir_all(parent)?;
+        }
+        let mut out = BufWriter::new(fs::File::create(path)?);
+        match format {
+            ChatExportFormat::Json => serde_json::to_writer_pretty(&mut out, &messages)?,
+            ChatExportFormat::Markdown => write_markdown(&mut out, &messages)?,
+        }
+        out.flush()?;
+        Ok(messages.len())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl From<StoredChatMessage> for ChatMessage {
+    fn from(message: StoredChatMessage) -> Self {
+        Self {
+            role: message.role,
+            content: message.content,
+            created_at: message.created_at,
+        }
+    }
+}
+
+fn write_markdown<W: Write>(out: &mut W, messages: &[StoredChatMessage]) -> Result<()> {
+    let mut current: Option<&str> = None;
+    for message in messages {
+        if current != Some(message.conversation_id.as_str()) {
+            current = Some(&message.conversation_id);
+            writeln!(out, "# Conversation {}\n", message.conversation_id)?;
+        }
+        let speaker = match message.role {
+            ChatRole::User => "You".to_string(),
+            ChatRole::Assistant => message
+                .model
+                .clone()
+                .unwrap_or_else(|| "Assistant".to_string()),
+            ChatRole::System => "System".to_string(),
+        };
+        writeln!(out, "## {speaker}\n")?;
+        if let Some(context) = &message.terminal_context {
+            writeln!(out, "Terminal context:\n\n```\n{context}\n```\n")?;
+        }
+        writeln!(out, "{}\n", message.content)?;
+    }
+    Ok(())
+}
+
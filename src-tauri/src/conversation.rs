@@ -0,0 +1,388 @@
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    sync::{Mutex, MutexGuard},
+};
+
+use anyhow::{bail, Result};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+use crate::unix_millis;
+
+/// Context window assumed when the caller does not say how large the model's
+/// is. Small enough for the models Ollama serves by default.
+pub const DEFAULT_CONTEXT_TOKENS: usize = 4096;
+pub const CONTEXT_TOKENS_MIN: usize = 512;
+pub const CONTEXT_TOKENS_MAX: usize = 131_072;
+/// Tokens kept free for the model's reply when fitting history.
+const REPLY_RESERVE_TOKENS: usize = 1024;
+/// Per-message overhead of the chat template, roughly.
+const MESSAGE_OVERHEAD_TOKENS: usize = 4;
+/// Older messages beyond this are dropped from storage altogether.
+const MESSAGES_PER_CONVERSATION_MAX: usize = 200;
+/// The least recently used conversation is forgotten past this many.
+const CONVERSATIONS_MAX: usize = 100;
+const TITLE_CHARS_MAX: usize = 60;
+
+/// Conversations with the assistant, keyed by id.
+pub static CONVERSATIONS: Lazy<ConversationStore> = Lazy::new(ConversationStore::default);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatRole {
+    System,
+    User,
+    Assistant,
+}
+
+impl ChatRole {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::System => "system",
+            Self::User => "user",
+            Self::Assistant => "assistant",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "system" => Some(Self::System),
+            "user" => Some(Self::User),
+            "assistant" => Some(Self::Assistant),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: ChatRole,
+    pub content: String,
+    /// Milliseconds since the Unix epoch.
+    #[serde(default)]
+    pub created_at: u64,
+}
+
+impl ChatMessage {
+    pub fn new(role: ChatRole, content: impl Into<String>) -> Self {
+        Self {
+            role,
+            content: content.into(),
+            created_at: unix_millis(),
+        }
+    }
+
+    /// Rough token count: about four characters per token for English text
+    /// and code, plus the template overhead.
+    pub fn estimated_tokens(&self) -> usize {
+        estimate_tokens(&self.content) + MESSAGE_OVERHEAD_TOKENS
+    }
+}
+
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Conversation {
+    pub id: String,
+    pub title: String,
+    pub model: Option<String>,
+    pub created_at: u64,
+    pub updated_at: u64,
+    /// User and assistant turns, oldest first. System prompts are supplied
+    /// with each request and not stored.
+    pub messages: Vec<ChatMessage>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConversationSummary {
+    pub id: String,
+    pub title: String,
+    pub model: Option<String>,
+    pub created_at: u64,
+    pub updated_at: u64,
+    pub message_count: usize,
+}
+
+impl From<&Conversation> for ConversationSummary {
+    fn from(conversation: &Conversation) -> Self {
+        Self {
+            id: conversation.id.clone(),
+            title: conversation.title.clone(),
+            model: conversation.model.clone(),
+            created_at: conversation.created_at,
+            updated_at: conversation.updated_at,
+            message_count: conversation.messages.len(),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ConversationStore {
+    conversations: Mutex<HashMap<String, Conversation>>,
+}
+
+impl ConversationStore {
+    /// Stored turns of a conversation, or none if it has not started yet.
+    pub fn history(&self, id: &str) -> Vec<ChatMessage> {
+        self.lock()
+            .get(id)
+            .map(|conversation| conversation.messages.clone())
+            .unwrap_or_default()
+    }
+
+    /// Seeds a conversation that is not in memory, such as one loaded from
+    /// the chat history after a restart.
+    pub fn restore(&self, id: &str, model: Option<&str>, messages: Vec<ChatMessage>) {
+        let Some(first) = messages.first() else {
+            return;
+        };
+        let created_at = first.created_at;
+        let updated_at = messages.last().map_or(created_at, |message| message.created_at);
+        let title = messages
+            .iter()
+            .find(|message| message.role == ChatRole::User)
+            .map(|message| title_from(&message.content))
+            .unwrap_or_default();
+        self.lock()
+            .entry(id.to_string())
+            .or_insert_with(|| Conversation {
+                id: id.to_string(),
+                title,
+                model: model.map(str::to_string),
+                created_at,
+                updated_at,
+                messages,
+            });
+    }
+
+    /// Appends a completed exchange, starting the conversation if needed.
+    pub fn record_turn(&self, id: &str, model: &str, prompt: &str, reply: &str) {
+        let now = unix_millis();
+        let mut conversations = self.lock();
+        let conversation = conversations
+            .entry(id.to_string())
+            .or_insert_with(|| Conversation {
+                id: id.to_string(),
+                title: title_from(prompt),
+                model: None,
+                created_at: now,
+                updated_at: now,
+                messages: Vec::new(),
+            });
+        conversation.model = Some(model.to_string());
+        conversation.updated_at = now;
+        conversation
+            .messages
+            .push(ChatMessage::new(ChatRole::User, prompt));
+        conversation
+            .messages
+            .push(ChatMessage::new(ChatRole::Assistant, reply));
+        let mut excess = conversation
+            .messages
+            .len()
+            .saturating_sub(MESSAGES_PER_CONVERSATION_MAX);
+        // Drop whole exchanges so the history still opens with a prompt.
+        while excess > 0
+            && conversation
+                .messages
+                .get(excess)
+                .is_some_and(|message| message.role != ChatRole::User)
+        {
+            excess += 1;
+        }
+        conversation.messages.drain(..excess);
+
+        if conversations.len() > CONVERSATIONS_MAX {
+            if let Some(oldest) = conversations
+                .values()
+                .min_by_key(|conversation| conversation.updated_at)
+                .map(|conversation| conversation.id.clone())
+            {
+                conversations.remove(&oldest);
+            }
+        }
+    }
+
+    /// Every conversation, most recently active first.
+    pub fn list(&self) -> Vec<ConversationSummary> {
+        let mut summaries: Vec<ConversationSummary> = self
+            .lock()
+            .values()
+            .map(ConversationSummary::from)
+            .collect();
+        summaries.sort_by_key(|summary| Reverse(summary.updated_at));
+        summaries
+    }
+
+    pub fn load(&self, id: &str) -> Result<Conversation> {
+        match self.lock().get(id) {
+            Some(conversation) => Ok(conversation.clone()),
+            None => bail!("conversation {id} not found"),
+        }
+    }
+
+    pub fn clear(&self, id: &str) -> Result<()> {
+        if self.lock().remove(id).is_none() {
+            bail!("conversation {id} not found");
+        }
+        Ok(())
+    }
+
+    pub fn clear_all(&self) {
+        self.lock().clear();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, Conversation>> {
+        self.conversations
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// The most recent part of `history` that fits in `budget` tokens. Whole
+/// exchanges are kept or dropped together, so the result never opens with an
+/// orphaned assistant reply.
+pub fn fit_history(history: &[ChatMessage], budget: usize) -> &[ChatMessage] {
+    let mut used = 0;
+    let mut start = history.len();
+    for (index, message) in history.iter().enumerate().rev() {
+        used += message.estimated_tokens();
+        if used > budget {
+            break;
+        }
+        start = index;
+    }
+    while history
+        .get(start)
+        .is_some_and(|message| message.role != ChatRole::User)
+    {
+        start += 1;
+    }
+    &history[start..]
+}
+
+/// Tokens left for stored history once the reply reserve and the messages
+/// that are always sent (`fixed`) are accounted for.
+pub fn history_budget(context_tokens: usize, fixed: &[ChatMessage]) -> usize {
+    let fixed: usize = fixed.iter().map(ChatMessage::estimated_tokens).sum();
+    context_tokens
+        .saturating_sub(REPLY_RESERVE_TOKENS)
+        .saturating_sub(fixed)
+}
+
+/// Conversation title: the first line of its opening prompt, shortened.
+pub(crate) fn title_from(prompt: &str) -> String {
+    let line = prompt.lines().next().unwrap_or_default().trim();
+    let mut title: String = line.chars().take(TITLE_CHARS_MAX).collect();
+    if line.chars().count() > TITLE_CHARS_MAX {
+        title.push('…');
+    }
+    title
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A message costing exactly `tokens` estimated tokens.
+    fn message(role: ChatRole, tokens: usize) -> ChatMessage {
+        let chars = (tokens - MESSAGE_OVERHEAD_TOKENS) * 4;
+        ChatMessage::new(role, "x".repeat(chars))
+    }
+
+    /// `turns` exchanges of a 10-token prompt and a 30-token reply.
+    fn exchanges(turns: usize) -> Vec<ChatMessage> {
+        (0..turns)
+            .flat_map(|_| {
+                [
+                    message(ChatRole::User, 10),
+                    message(ChatRole::Assistant, 30),
+                ]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fit_history_keeps_whole_recent_exchanges() {
+        let history = exchanges(3);
+        assert_eq!(fit_history(&history, 120).len(), 6);
+        assert_eq!(fit_history(&history, 119).len(), 4);
+        assert_eq!(fit_history(&history, 79).len(), 2);
+        // The last reply alone would fit, but not without its prompt.
+        assert!(fit_history(&history, 39).is_empty());
+        assert!(fit_history(&history, 0).is_empty());
+    }
+
+    #[test]
+    fn fit_history_never_opens_with_a_reply() {
+        let mut history = exchanges(2);
+        history.remove(0);
+        let fitted = fit_history(&history, 1000);
+        assert_eq!(fitted.len(), 2);
+        assert_eq!(fitted[0].role, ChatRole::User);
+    }
+
+    #[test]
+    fn oversized_fixed_messages_leave_no_history_budget() {
+        let fixed = [message(ChatRole::System, DEFAULT_CONTEXT_TOKENS)];
+        let budget = history_budget(DEFAULT_CONTEXT_TOKENS, &fixed);
+        assert_eq!(budget, 0);
+        assert!(fit_history(&exchanges(3), budget).is_empty());
+
+        let fixed = [message(ChatRole::System, 100)];
+        assert_eq!(
+            history_budget(DEFAULT_CONTEXT_TOKENS, &fixed),
+            DEFAULT_CONTEXT_TOKENS - REPLY_RESERVE_TOKENS - 100
+        );
+    }
+
+    #[test]
+    fn record_turn_keeps_the_newest_messages() {
+        let store = ConversationStore::default();
+        let turns = MESSAGES_PER_CONVERSATION_MAX / 2 + 5;
+        for turn in 0..turns {
+            store.record_turn("c", "llama3", &format!("q{turn}"), &format!("a{turn}"));
+        }
+
+        let messages = store.history("c");
+        assert_eq!(messages.len(), MESSAGES_PER_CONVERSATION_MAX);
+        assert_eq!(messages[0].role, ChatRole::User);
+        assert_eq!(messages[0].content, "q5");
+        assert_eq!(messages.last().unwrap().content, format!("a{}", turns - 1));
+        assert_eq!(store.load("c").unwrap().title, "q0");
+    }
+
+    #[test]
+    fn record_turn_trims_whole_exchanges_from_an_odd_history() {
+        let store = ConversationStore::default();
+        let mut restored = exchanges(MESSAGES_PER_CONVERSATION_MAX / 2);
+        restored.pop();
+        store.restore("c", None, restored);
+
+        store.record_turn("c", "llama3", "next", "reply");
+        let messages = store.history("c");
+        assert_eq!(messages.len(), MESSAGES_PER_CONVERSATION_MAX - 1);
+        assert_eq!(messages[0].role, ChatRole::User);
+        assert_eq!(messages.last().unwrap().content, "reply");
+    }
+
+    #[test]
+    fn least_recently_active_conversation_is_forgotten() {
+        let store = ConversationStore::default();
+        for index in 0..CONVERSATIONS_MAX {
+            let mut prompt = message(ChatRole::User, 10);
+            prompt.created_at = index as u64 + 1;
+            store.restore(&format!("c{index}"), None, vec![prompt]);
+        }
+
+        store.record_turn("new", "llama3", "hi", "hello");
+        let ids: Vec<String> = store.list().into_iter().map(|summary| summary.id).collect();
+        assert_eq!(ids.len(), CONVERSATIONS_MAX);
+        assert_eq!(ids[0], "new");
+        assert!(!ids.contains(&"c0".to_string()));
+        assert!(ids.contains(&"c1".to_string()));
+    }
+}
@@ -0,0 +1,316 @@
+use std::io::Write;
+
+use anyhow::Result;
+use serde::Deserialize;
+
+use crate::{
+    screen::{Cell, Color, Line, Style, TerminalScreen},
+    scrollback::DiskScrollback,
+};
+
+const HTML_FOREGROUND: &str = "#d4d4d4";
+const HTML_BACKGROUND: &str = "#1e1e1e";
+
+/// xterm's default values for the 16 standard colours.
+const ANSI_PALETTE: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// Plain text with all styling removed.
+    Txt,
+    /// Text with SGR colour and attribute sequences.
+    Ansi,
+    /// A standalone HTML page with styled spans.
+    Html,
+}
+
+/// Writes everything the session has stored, oldest first. Rows that were
+/// moved to disk scrollback only kept their text, so they export unstyled.
+pub fn write<W: Write>(
+    out: &mut W,
+    format: ExportFormat,
+    spill: Option<&DiskScrollback>,
+    screen: &TerminalScreen,
+) -> Result<()> {
+    if let ExportFormat::Html = format {
+        write!(
+            out,
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Termalime export</title>\n</head>\n<body style=\"margin:0;background:{HTML_BACKGROUND}\">\n<pre style=\"margin:0;padding:1em;color:{HTML_FOREGROUND};background:{HTML_BACKGROUND};font-family:monospace\">"
+        )?;
+    }
+
+    if let Some(spill) = spill {
+        let mut result = Ok(());
+        spill.scan(|_, text| {
+            result = match format {
+                ExportFormat::Html => writeln!(out, "{}", escape_html(text)),
+                _ => writeln!(out, "{text}"),
+            };
+            result.is_ok()
+        })?;
+        result?;
+    }
+
+    match format {
+        ExportFormat::Txt => {
+            for line in screen.lines() {
+                writeln!(out, "{line}")?;
+            }
+        }
+        ExportFormat::Ansi | ExportFormat::Html => {
+            for row in screen.rows() {
+                write_row(out, format, row)?;
+            }
+        }
+    }
+
+    if let ExportFormat::Html = format {
+        writeln!(out, "</pre>\n</body>\n</html>")?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn write_row<W: Write>(out: &mut W, format: ExportFormat, row: &Line) -> Result<()> {
+    // Wrapped rows continue on the next row, so their trailing cells matter.
+    let len = if row.wrapped {
+        row.cells.len()
+    } else {
+        row.content_len()
+    };
+    let cells = row.cells[..len]
+        .iter()
+        .filter(|cell| !cell.is_wide_spacer());
+
+    let mut current: Option<Style> = None;
+    let mut run = String::new();
+    for cell in cells {
+        if current != Some(cell.style) {
+            flush_run(out, format, current, &mut run)?;
+            current = Some(cell.style);
+        }
+        push_cell(&mut run, format, cell);
+    }
+    flush_run(out, format, current, &mut run)?;
+
+    if !row.wrapped {
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+fn push_cell(run: &mut String, format: ExportFormat, cell: &Cell) {
+    match (format, cell.ch) {
+        (ExportFormat::Html, '&') => run.push_str("&amp;"),
+        (ExportFormat::Html, '<') => run.push_str("&lt;"),
+        (ExportFormat::Html, '>') => run.push_str("&gt;"),
+        (_, ch) => run.push(ch),
+    }
+}
+
+fn flush_run<W: Write>(
+    out: &mut W,
+    format: ExportFormat,
+    style: Option<Style>,
+    run: &mut String,
+) -> Result<()> {
+    if run.is_empty() {
+        return Ok(());
+    }
+    let style = style.unwrap_or_default();
+    match format {
+        ExportFormat::Txt => write!(out, "{run}")?,
+        ExportFormat::Ansi if style == Style::default() => write!(out, "{run}")?,
+        ExportFormat::Ansi => write!(out, "\x1b[{}m{run}\x1b[0m", sgr(&style))?,
+        ExportFormat::Html => match css(&style) {
+            Some(css) => write!(out, "<span style=\"{css}\">{run}</span>")?,
+            None => write!(out, "{run}")?,
+        },
+    }
+    run.clear();
+    Ok(())
+}
+
+pub(crate) fn sgr(style: &Style) -> String {
+    let mut codes: Vec<String> = Vec::new();
+    for (enabled, code) in [
+        (style.bold, "1"),
+        (style.dim, "2"),
+        (style.italic, "3"),
+        (style.underline, "4"),
+        (style.inverse, "7"),
+        (style.strikethrough, "9"),
+    ] {
+        if enabled {
+            codes.push(code.to_string());
+        }
+    }
+    for (color, base) in [(style.fg, 30), (style.bg, 40)] {
+        match color {
+            Color::Default => {}
+            Color::Indexed(n) if n < 8 => codes.push((base + n as u16).to_string()),
+            Color::Indexed(n) if n < 16 => codes.push((base + 60 + (n - 8) as u16).to_string()),
+            Color::Indexed(n) => codes.push(format!("{};5;{n}", base + 8)),
+            Color::Rgb(r, g, b) => codes.push(format!("{};2;{r};{g};{b}", base + 8)),
+        }
+    }
+    codes.join(";")
+}
+
+fn css(style: &Style) -> Option<String> {
+    let (mut fg, mut bg) = (hex(style.fg), hex(style.bg));
+    if style.inverse {
+        (fg, bg) = (
+            bg.or(Some(HTML_BACKGROUND.to_string())),
+            fg.or(Some(HTML_FOREGROUND.to_string())),
+        );
+    }
+
+    let mut rules = Vec::new();
+    if let Some(fg) = fg {
+        rules.push(format!("color:{fg}"));
+    }
+    if let Some(bg) = bg {
+        rules.push(format!("background-color:{bg}"));
+    }
+    if style.bold {
+        rules.push("font-weight:bold".to_string());
+    }
+    if style.dim {
+        rules.push("opacity:0.7".to_string());
+    }
+    if style.italic {
+        rules.push("font-style:italic".to_string());
+    }
+    match (style.underline, style.strikethrough) {
+        (true, true) => rules.push("text-decoration:underline line-through".to_string()),
+        (true, false) => rules.push("text-decoration:underline".to_string()),
+        (false, true) => rules.push("text-decoration:line-through".to_string()),
+        (false, false) => {}
+    }
+    (!rules.is_empty()).then(|| rules.join(";"))
+}
+
+fn hex(color: Color) -> Option<String> {
+    let (r, g, b) = match color {
+        Color::Default => return None,
+        Color::Indexed(n) => indexed_rgb(n),
+        Color::Rgb(r, g, b) => (r, g, b),
+    };
+    Some(format!("#{r:02x}{g:02x}{b:02x}"))
+}
+
+/// Maps an xterm 256-colour index to RGB.
+fn indexed_rgb(n: u8) -> (u8, u8, u8) {
+    match n {
+        0..=15 => ANSI_PALETTE[n as usize],
+        16..=231 => {
+            let level = |value: u8| if value == 0 { 0 } else { 55 + value * 40 };
+            let n = n - 16;
+            (level(n / 36), level((n / 6) % 6), level(n % 6))
+        }
+        _ => {
+            let gray = 8 + (n - 232) * 10;
+            (gray, gray, gray)
+        }
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 12x3 screen with a plain word, a bold red word, markup characters
+    /// and a row that only has a coloured background after its text.
+    fn known_screen() -> TerminalScreen {
+        let mut screen = TerminalScreen::new(12, 3, 0);
+        screen.process(b"ok \x1b[1;31mred\x1b[0m\r\n<a&b>\r\nx\x1b[44m  \x1b[0m");
+        screen
+    }
+
+    fn export(format: ExportFormat) -> String {
+        let mut out = Vec::new();
+        write(&mut out, format, None, &known_screen()).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn txt_drops_styling_and_trailing_blanks() {
+        assert_eq!(export(ExportFormat::Txt), "ok red\n<a&b>\nx\n");
+    }
+
+    #[test]
+    fn ansi_keeps_styled_runs_and_coloured_trailing_cells() {
+        assert_eq!(
+            export(ExportFormat::Ansi),
+            "ok \x1b[1;31mred\x1b[0m\n<a&b>\nx\x1b[44m  \x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn html_escapes_markup_and_styles_runs() {
+        let html = export(ExportFormat::Html);
+        let body = html
+            .split_once("font-family:monospace\">")
+            .and_then(|(_, rest)| rest.split_once("</pre>"))
+            .map(|(body, _)| body)
+            .unwrap();
+        assert_eq!(
+            body,
+            "ok <span style=\"color:#cd0000;font-weight:bold\">red</span>\n\
+             &lt;a&amp;b&gt;\n\
+             x<span style=\"background-color:#0000ee\">  </span>\n"
+        );
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.ends_with("</html>\n"));
+    }
+
+    #[test]
+    fn escape_html_replaces_ampersand_first() {
+        assert_eq!(
+            escape_html("a < b && c > d"),
+            "a &lt; b &amp;&amp; c &gt; d"
+        );
+        assert_eq!(escape_html("&lt;"), "&amp;lt;");
+    }
+
+    #[test]
+    fn trailing_cells_match_redraw() {
+        let screen = known_screen();
+        let rows: Vec<&Line> = screen.rows().collect();
+        assert_eq!(
+            rows.iter().map(|row| row.content_len()).collect::<Vec<_>>(),
+            [6, 5, 3]
+        );
+        let mut underlined = TerminalScreen::new(12, 1, 0);
+        underlined.process(b"a\x1b[4m \x1b[0m");
+        assert!(underlined.redraw().contains("\x1b[0;4m \x1b[0m"));
+        let mut out = Vec::new();
+        write(&mut out, ExportFormat::Ansi, None, &underlined).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "a\x1b[4m \x1b[0m\n");
+    }
+}
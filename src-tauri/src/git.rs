@@ -0,0 +1,71 @@
+use std::{
+    path::Path,
+    process::{Command, Stdio},
+};
+
+/// Summary of the repository containing a directory.
+#[derive(Debug, Clone, Default)]
+pub struct GitStatus {
+    pub branch: Option<String>,
+    pub dirty: bool,
+    /// Commits ahead of / behind the upstream, when one is configured.
+    pub ahead: Option<u32>,
+    pub behind: Option<u32>,
+    pub stash_count: u32,
+    pub commit_subject: Option<String>,
+}
+
+/// Collects git status for `dir`, or `None` when it is not inside a work tree.
+pub fn status(dir: &Path) -> Option<GitStatus> {
+    let porcelain = run_git(dir, &["status", "--porcelain=v2", "--branch"])?;
+    let mut status = GitStatus::default();
+    let mut head_oid: Option<String> = None;
+
+    for line in porcelain.lines() {
+        if let Some(head) = line.strip_prefix("# branch.head ") {
+            if head != "(detached)" {
+                status.branch = Some(head.to_string());
+            }
+        } else if let Some(oid) = line.strip_prefix("# branch.oid ") {
+            if oid != "(initial)" {
+                head_oid = Some(oid.chars().take(7).collect());
+            }
+        } else if let Some(counts) = line.strip_prefix("# branch.ab ") {
+            let mut parts = counts.split_whitespace();
+            status.ahead = parts
+                .next()
+                .and_then(|value| value.trim_start_matches('+').parse().ok());
+            status.behind = parts
+                .next()
+                .and_then(|value| value.trim_start_matches('-').parse().ok());
+        } else if !line.starts_with('#') && !line.trim().is_empty() {
+            status.dirty = true;
+        }
+    }
+
+    if status.branch.is_none() {
+        status.branch = head_oid.map(|oid| format!("detached@{oid}"));
+    }
+
+    status.stash_count = run_git(dir, &["rev-list", "--walk-reflogs", "--count", "refs/stash"])
+        .and_then(|count| count.trim().parse().ok())
+        .unwrap_or(0);
+
+    status.commit_subject = run_git(dir, &["log", "-1", "--format=%s"])
+        .map(|subject| subject.trim().to_string())
+        .filter(|subject| !subject.is_empty());
+
+    Some(status)
+}
+
+fn run_git(dir: &Path, args: &[&str]) -> Option<String> {
+    Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+}
@@ -0,0 +1,123 @@
+use std::{
+    collections::VecDeque,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{Context, Result};
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+
+use crate::shell_integration::RecordedCommand;
+
+/// File name of the command history log inside the app data directory.
+pub const HISTORY_FILE: &str = "command_history.jsonl";
+/// Size at which the log is rotated to `<file>.1`.
+const HISTORY_ROTATE_BYTES: u64 = 32 * 1024 * 1024;
+
+/// Process-wide history log, initialised once the app data dir is known.
+pub static COMMAND_HISTORY: OnceCell<CommandHistoryStore> = OnceCell::new();
+
+/// One line of the history log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub session_id: String,
+    #[serde(flatten)]
+    pub record: RecordedCommand,
+}
+
+/// Append-only JSON Lines log of finished commands across all sessions.
+pub struct CommandHistoryStore {
+    path: PathBuf,
+    write_lock: Mutex<()>,
+}
+
+impl CommandHistoryStore {
+    pub fn new(data_dir: &Path) -> Self {
+        Self {
+            path: data_dir.join(HISTORY_FILE),
+            write_lock: Mutex::new(()),
+        }
+    }
+
+    pub fn append(&self, session_id: &str, record: &RecordedCommand) -> Result<()> {
+        let _guard = self.write_lock.lock().unwrap_or_else(|err| err.into_inner());
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create {}", parent.display()))?;
+        }
+        if fs::metadata(&self.path).is_ok_and(|meta| meta.len() > HISTORY_ROTATE_BYTES) {
+            fs::rename(&self.path, self.path.with_extension("jsonl.1"))
+                .with_context(|| format!("failed to rotate {}", self.path.display()))?;
+        }
+
+        let entry = HistoryEntry {
+            session_id: session_id.to_string(),
+            record: record.clone(),
+        };
+        let mut line = serde_json::to_string(&entry)?;
+        line.push('\n');
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(line.as_bytes()))
+            .with_context(|| format!("failed to append to {}", self.path.display()))
+    }
+
+    /// Most recent entries for a session, oldest first.
+    pub fn for_session(&self, session_id: &str, limit: usize) -> Result<Vec<HistoryEntry>> {
+        self.scan(limit, |entry| entry.session_id == session_id)
+    }
+
+    pub fn find(&self, session_id: &str, command_id: u64) -> Result<Option<HistoryEntry>> {
+        Ok(self
+            .scan(1, |entry| {
+                entry.session_id == session_id && entry.record.command.id == command_id
+            })?
+            .pop())
+    }
+
+    /// Case-insensitive substring search over command lines and output.
+    pub fn search(&self, query: &str, limit: usize) -> Result<Vec<HistoryEntry>> {
+        let needle = query.to_lowercase();
+        self.scan(limit, |entry| {
+            entry.record.command.command.to_lowercase().contains(&needle)
+                || entry.record.output.to_lowercase().contains(&needle)
+        })
+    }
+
+    /// Returns the last `limit` matching entries across the rotated and
+    /// current logs, oldest first.
+    fn scan<F>(&self, limit: usize, mut matches: F) -> Result<Vec<HistoryEntry>>
+    where
+        F: FnMut(&HistoryEntry) -> bool,
+    {
+        let mut found = VecDeque::with_capacity(limit.min(1024));
+        for path in [self.path.with_extension("jsonl.1"), self.path.clone()] {
+            let file = match File::open(&path) {
+                Ok(file) => file,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(err) => {
+                    return Err(err).with_context(|| format!("failed to open {}", path.display()))
+                }
+            };
+            for line in BufReader::new(file).lines() {
+                let line = line.with_context(|| format!("failed to read {}", path.display()))?;
+                // Skip lines torn by a crash mid-write rather than failing the scan.
+                let Ok(entry) = serde_json::from_str::<HistoryEntry>(&line) else {
+                    continue;
+                };
+                if matches(&entry) {
+                    found.push_back(entry);
+                    if found.len() > limit {
+                        found.pop_front();
+                    }
+                }
+            }
+        }
+        Ok(found.into())
+    }
+}
@@ -1,78 +1,204 @@
+pub mod chat_history;
+pub mod conversation;
+pub mod export;
+pub mod git;
+pub mod history;
+pub mod ollama;
+pub mod output;
+pub mod profiles;
 pub mod pty;
-
-use std::{collections::HashMap, io::Read, sync::Arc, time::Duration};
+pub mod recording;
+pub mod replay;
+pub mod screen;
+pub mod scrollback;
+pub mod shell_integration;
+pub mod snapshot;
+pub mod utf8;
+
+use std::{
+    collections::HashMap,
+    io::Read,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 use tokio::sync::Mutex;
 
-const TERMINAL_BUFFER_MAX: usize = 16 * 1024;
 const TERMINAL_LINES_MAX: usize = 400;
+const COMMAND_HISTORY_LIMIT_MAX: usize = 500;
+const TERMINAL_SEARCH_HITS_MAX: usize = 1000;
+const TERMINAL_SEARCH_CONTEXT_MAX: usize = 20;
+const CHAT_SEARCH_LIMIT_MAX: usize = 500;
+const PTY_KILL_GRACE: Duration = Duration::from_secs(3);
+/// How long `kill_pty` waits for the session's reader to drain.
+const READER_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
 const DEFAULT_PREFLIGHT_MODEL: &str = "gemma3:270m";
 const PREFLIGHT_SYSTEM_PROMPT: &str = "You are a senior security operations (SOC) analyst. Your job is to analyze a shell command for potential risks. Do not be conversational. Respond only in JSON with the following keys: summary (one sentence), is_risky (true/false), risk_reason (one paragraph), safe_alternative (optional string offering a safer approach).";
 const PREFLIGHT_REPAIR_PROMPT: &str = "You are a JSON repair bot. Convert the provided text into valid JSON with the keys summary (string), is_risky (boolean), risk_reason (string), and safe_alternative (string, optional). Respond with JSON only.";
 const PREFLIGHT_TEXT_PROMPT: &str = "You are a senior SOC analyst. Provide a concise assessment of a shell command using exactly three plain-text lines, no code fences or quoting: (1) 'Summary: <what the command does>' (2) 'Likelihood of maliciousness: <percentage 0-100>' (3) 'Rationale: <explain how an attacker could abuse the command or why it's risky>'. Keep the rationale focused on potential malicious impact rather than benign behavior.";
 
 use anyhow::Error;
-use futures_util::StreamExt;
-use once_cell::sync::Lazy;
-use pty::{PtySize, PTY_REGISTRY};
-use reqwest::Client;
+use base64::prelude::{Engine as _, BASE64_STANDARD};
+use chat_history::{
+    ChatExportFormat, ChatHistoryStore, ChatSearchHit, NewChatMessage, StoredChatMessage,
+    CHAT_HISTORY,
+};
+use conversation::{
+    ChatMessage, ChatRole, Conversation, ConversationSummary, CONTEXT_TOKENS_MAX,
+    CONTEXT_TOKENS_MIN, CONVERSATIONS, DEFAULT_CONTEXT_TOKENS,
+};
+use export::ExportFormat;
+use futures_util::{
+    future::{AbortHandle, Abortable},
+    StreamExt,
+};
+use ollama::{OllamaClient, OllamaSettingsStore, OllamaSettingsUpdate, OllamaSettingsView};
+use output::{Delivery, FlowStats, OUTPUT_FLOWS};
+use profiles::{ProfileStore, TerminalProfile};
+use history::{CommandHistoryStore, HistoryEntry, COMMAND_HISTORY};
+use shell_integration::{RecordedCommand, ShellCommand, ShellKind};
+use pty::{PtyExitStatus, PtySignal, PtySize, SessionInfo, SpawnOptions, SyncGroup, PTY_REGISTRY};
+use recording::{CastHeader, CastRecorder, RecorderHandle, RecordingSummary, RECORDINGS};
+use regex::RegexBuilder;
+use replay::{CastFile, ReplayAction, ReplayEvent, ReplayStatus, REPLAYS};
+use scrollback::{DiskScrollback, ScrollbackConfig};
 use serde::{de::Error as _, Deserialize, Serialize};
 use serde_json::json;
+use snapshot::{SnapshotHandle, SnapshotStore, TerminalSearchResults, TerminalSnapshot};
 use tauri::{AppHandle, Emitter, Manager, State};
-static HTTP_CLIENT: Lazy<Client> = Lazy::new(|| {
-    Client::builder()
-        .timeout(Duration::from_secs(30))
-        .build()
-        .expect("failed to initialize reqwest client")
-});
+use utf8::Utf8Decoder;
+
+/// Milliseconds since the Unix epoch, the unit of every stored timestamp.
+pub(crate) fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default()
+}
 
 type ReaderHandle = tauri::async_runtime::JoinHandle<()>;
 
 #[derive(Default)]
 struct AppState {
     readers: Arc<Mutex<HashMap<String, ReaderHandle>>>,
-    terminal_snapshots: Arc<Mutex<HashMap<String, TerminalSnapshot>>>,
+    terminal_snapshots: Arc<SnapshotStore>,
+    /// Abort handles for `ask_ollama` streams, keyed by request id.
+    ollama_streams: Arc<Mutex<HashMap<String, AbortHandle>>>,
+}
+
+#[derive(Serialize)]
+struct TerminalContextPayload {
+    session_id: String,
+    last_lines: String,
+    /// Lines between the returned page and the bottom of the output.
+    offset: usize,
+    total_lines: usize,
 }
 
-#[derive(Default, Clone)]
-struct TerminalSnapshot {
-    buffer: String,
+#[derive(Serialize, Clone)]
+struct TerminalOutputPayload {
+    session_id: String,
+    data: String,
+    encoding: OutputEncoding,
+    /// Raw PTY bytes behind `data`; the amount to pass to `ack_terminal_output`.
+    bytes: usize,
 }
 
-impl TerminalSnapshot {
-    fn append(&mut self, chunk: &str) {
-        self.buffer.push_str(chunk);
-        if self.buffer.len() > TERMINAL_BUFFER_MAX {
-            let excess = self.buffer.len() - TERMINAL_BUFFER_MAX;
-            self.buffer.drain(..excess);
+impl TerminalOutputPayload {
+    fn text(session_id: &str, data: String) -> Self {
+        Self {
+            session_id: session_id.to_string(),
+            bytes: data.len(),
+            data,
+            encoding: OutputEncoding::Utf8,
         }
     }
+}
+
+#[derive(Serialize, Clone)]
+struct TerminalFlowPayload {
+    session_id: String,
+    #[serde(flatten)]
+    stats: FlowStats,
+}
 
-    fn last_lines(&self, limit: usize) -> String {
-        if self.buffer.is_empty() {
-            return String::new();
+/// How `terminal-output` carries PTY bytes to the frontend.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum OutputEncoding {
+    /// Decoded text; characters split across reads are held back until whole.
+    #[default]
+    Utf8,
+    /// The exact bytes read from the PTY, base64-encoded.
+    Base64,
+}
+
+impl OutputEncoding {
+    /// Encodes the next `bytes` of a stream for `terminal-output`.
+    fn encode(self, decoder: &mut Utf8Decoder, bytes: &[u8]) -> String {
+        match self {
+            Self::Utf8 => decoder.decode(bytes),
+            Self::Base64 => BASE64_STANDARD.encode(bytes),
         }
-        let lines: Vec<&str> = self.buffer.lines().rev().take(limit).collect();
-        lines.into_iter().rev().collect::<Vec<_>>().join("\n")
     }
 }
 
+/// How a session delivers output to the frontend.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+struct OutputOptions {
+    #[serde(default, rename = "output_encoding")]
+    encoding: OutputEncoding,
+    /// The frontend acknowledges output with `ack_terminal_output`, and the
+    /// reader pauses when too much is unacknowledged.
+    #[serde(default)]
+    flow_control: bool,
+}
+
+#[derive(Serialize, Clone)]
+struct TerminalResizePayload {
+    session_id: String,
+    cols: u16,
+    rows: u16,
+}
+
+#[derive(Serialize, Clone)]
+struct ReplayStatePayload {
+    session_id: String,
+    #[serde(flatten)]
+    status: ReplayStatus,
+}
+
 #[derive(Serialize)]
-struct TerminalContextPayload {
+struct ReplayInfo {
     session_id: String,
-    last_lines: String,
+    title: Option<String>,
+    cols: u16,
+    rows: u16,
+    duration_ms: u64,
 }
 
 #[derive(Serialize, Clone)]
-struct TerminalOutputPayload {
+struct TerminalCommandPayload {
     session_id: String,
-    data: String,
+    command: ShellCommand,
+}
+
+#[derive(Serialize, Clone)]
+struct TerminalExitPayload {
+    session_id: String,
+    #[serde(flatten)]
+    status: PtyExitStatus,
 }
 
 #[derive(Serialize, Clone)]
 struct OllamaChunkPayload {
+    request_id: String,
+    conversation_id: String,
     content: Option<String>,
     done: bool,
     error: Option<String>,
+    /// The stream was stopped by `cancel_ollama`.
+    cancelled: bool,
 }
 
 #[derive(Deserialize)]
@@ -84,6 +210,95 @@ struct ResizeRequest {
     pixel_height: Option<u16>,
 }
 
+#[derive(Deserialize, Default)]
+struct SpawnRequest {
+    program: Option<String>,
+    #[serde(default)]
+    args: Vec<String>,
+    cwd: Option<String>,
+    #[serde(default)]
+    env: HashMap<String, String>,
+    cols: Option<u16>,
+    rows: Option<u16>,
+    /// Loads the OSC 133 integration script into bash, zsh and fish so
+    /// commands are segmented. On unless set to `false`; other shells are
+    /// left alone either way.
+    shell_integration: Option<bool>,
+    #[serde(default)]
+    scrollback: ScrollbackConfig,
+    #[serde(flatten)]
+    output: OutputOptions,
+}
+
+impl From<SpawnRequest> for SpawnOptions {
+    fn from(request: SpawnRequest) -> Self {
+        Self {
+            program: request
+                .program
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty()),
+            args: request.args,
+            cwd: request
+                .cwd
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+                .map(PathBuf::from),
+            env: request.env,
+            term: None,
+            size: initial_size(request.cols, request.rows),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ProfileSpawnRequest {
+    name: String,
+    cols: Option<u16>,
+    rows: Option<u16>,
+    #[serde(flatten)]
+    output: OutputOptions,
+}
+
+fn initial_size(cols: Option<u16>, rows: Option<u16>) -> PtySize {
+    let default_size = PtySize::default();
+    PtySize {
+        cols: cols.filter(|cols| *cols > 0).unwrap_or(default_size.cols),
+        rows: rows.filter(|rows| *rows > 0).unwrap_or(default_size.rows),
+        ..default_size
+    }
+}
+
+#[derive(Deserialize)]
+struct RenameRequest {
+    session_id: String,
+    title: String,
+}
+
+#[derive(Deserialize)]
+struct KillRequest {
+    session_id: String,
+    signal: Option<PtySignal>,
+}
+
+#[derive(Deserialize)]
+struct ReplayControlRequest {
+    session_id: String,
+    #[serde(flatten)]
+    action: ReplayAction,
+}
+
+#[derive(Deserialize)]
+struct StartRecordingRequest {
+    session_id: String,
+    /// Destination `.cast` file; defaults to the app data `recordings` dir.
+    path: Option<String>,
+    title: Option<String>,
+    /// Also record keystrokes sent to the PTY. Off by default, like
+    /// `asciinema rec`, since input can contain passwords.
+    #[serde(default)]
+    record_input: bool,
+}
+
 #[derive(Deserialize)]
 struct AskOllamaRequest {
     prompt: String,
@@ -91,6 +306,25 @@ struct AskOllamaRequest {
     system_prompt: Option<String>,
     persona_prompt: Option<String>,
     terminal_context: Option<String>,
+    /// Continues this conversation, or starts one with this id. A new id is
+    /// generated when omitted.
+    conversation_id: Option<String>,
+    /// The model's context window in tokens. Also sent as `num_ctx`.
+    context_window: Option<usize>,
+    /// Persona name, saved with the exchange in the chat history.
+    persona: Option<String>,
+    /// Tags this stream's `ollama-chunk` events and lets `cancel_ollama` stop
+    /// it. Generated when omitted.
+    request_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DeleteChatHistoryRequest {
+    conversation_id: Option<String>,
+    message_id: Option<i64>,
+    /// Required to delete everything when neither id is given.
+    #[serde(default)]
+    all: bool,
 }
 
 #[derive(Deserialize)]
@@ -132,11 +366,180 @@ struct WriteRequest {
     data: String,
 }
 
+#[derive(Deserialize)]
+struct BroadcastRequest {
+    session_ids: Vec<String>,
+    data: String,
+}
+
+#[derive(Serialize)]
+struct BroadcastOutcome {
+    session_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SyncGroupRequest {
+    name: String,
+    /// An empty list removes the group.
+    session_ids: Vec<String>,
+}
+
+#[tauri::command]
+async fn spawn_pty(
+    state: State<'_, AppState>,
+    app_handle: AppHandle,
+    request: Option<SpawnRequest>,
+) -> Result<String, String> {
+    let request = request.unwrap_or_default();
+    let shell_integration = request.shell_integration;
+    let scrollback = request.scrollback.clone();
+    let output = request.output;
+    let mut options = SpawnOptions::from(request);
+    match shell_integration {
+        Some(true) => inject_shell_integration(&app_handle, &mut options)?,
+        // Asked for by default only, so a failure just costs segmentation.
+        None => {
+            if let Err(err) = inject_shell_integration(&app_handle, &mut options) {
+                eprintln!("starting shell without integration: {err}");
+            }
+        }
+        Some(false) => {}
+    }
+    start_session(&state, app_handle, options, &scrollback, output).await
+}
+
+#[tauri::command]
+async fn spawn_pty_with_profile(
+    state: State<'_, AppState>,
+    app_handle: AppHandle,
+    request: ProfileSpawnRequest,
+) -> Result<String, String> {
+    let store = profile_store(&app_handle)?;
+    let name = request.name;
+    let profile = tauri::async_runtime::spawn_blocking(move || store.get(&name))
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())?;
+
+    let mut options = profile.spawn_options(initial_size(request.cols, request.rows));
+    if profile.shell_integration {
+        inject_shell_integration(&app_handle, &mut options)?;
+    }
+    let session_id = start_session(
+        &state,
+        app_handle,
+        options,
+        &profile.scrollback,
+        request.output,
+    )
+    .await?;
+
+    if !profile.startup_commands.is_empty() {
+        let id = session_id.clone();
+        let mut input = String::new();
+        for command in &profile.startup_commands {
+            input.push_str(command);
+            input.push('\r');
+        }
+        tauri::async_runtime::spawn_blocking(move || {
+            PTY_REGISTRY.with_session(&id, |session| session.write(input.as_bytes()))
+        })
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())?;
+    }
+
+    Ok(session_id)
+}
+
 #[tauri::command]
-async fn spawn_pty(state: State<'_, AppState>, app_handle: AppHandle) -> Result<String, String> {
-    let (session_id, reader) = tauri::async_runtime::spawn_blocking(|| {
-        let size = PtySize::default();
-        let session_id = PTY_REGISTRY.create_session(size, None)?;
+async fn list_profiles(app_handle: AppHandle) -> Result<Vec<TerminalProfile>, String> {
+    let store = profile_store(&app_handle)?;
+    tauri::async_runtime::spawn_blocking(move || store.list())
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn save_profile(
+    app_handle: AppHandle,
+    profile: TerminalProfile,
+) -> Result<TerminalProfile, String> {
+    let store = profile_store(&app_handle)?;
+    tauri::async_runtime::spawn_blocking(move || store.save(profile))
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn delete_profile(app_handle: AppHandle, name: String) -> Result<bool, String> {
+    let store = profile_store(&app_handle)?;
+    tauri::async_runtime::spawn_blocking(move || store.delete(&name))
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+fn get_shell_integration_script(shell: String) -> Result<String, String> {
+    ShellKind::detect(&shell)
+        .map(|kind| kind.script().to_string())
+        .ok_or_else(|| format!("no shell integration available for {shell}"))
+}
+
+fn inject_shell_integration(app_handle: &AppHandle, options: &mut SpawnOptions) -> Result<(), String> {
+    let scripts_dir = app_handle
+        .path()
+        .app_cache_dir()
+        .map_err(|err| err.to_string())?
+        .join("shell-integration");
+    shell_integration::inject(options, &scripts_dir).map_err(|err| err.to_string())
+}
+
+/// Creates the scrollback ring for a session under the cache dir. Failures are
+/// logged and leave the session with in-memory scrollback only.
+async fn open_disk_scrollback(
+    app_handle: &AppHandle,
+    session_id: &str,
+    capacity: u64,
+) -> Option<DiskScrollback> {
+    let path = match app_handle.path().app_cache_dir() {
+        Ok(dir) => dir.join("scrollback").join(format!("{session_id}.txt")),
+        Err(err) => {
+            eprintln!("failed to resolve scrollback dir: {err}");
+            return None;
+        }
+    };
+    tauri::async_runtime::spawn_blocking(move || DiskScrollback::create(&path, capacity))
+        .await
+        .map_err(Error::from)
+        .and_then(|result| result)
+        .inspect_err(|err| eprintln!("failed to create disk scrollback: {err:#}"))
+        .ok()
+}
+
+fn profile_store(app_handle: &AppHandle) -> Result<ProfileStore, String> {
+    let config_dir = app_handle
+        .path()
+        .app_config_dir()
+        .map_err(|err| err.to_string())?;
+    Ok(ProfileStore::new(&config_dir))
+}
+
+async fn start_session(
+    state: &AppState,
+    app_handle: AppHandle,
+    options: SpawnOptions,
+    scrollback: &ScrollbackConfig,
+    output: OutputOptions,
+) -> Result<String, String> {
+    let size = options.size;
+    let (session_id, reader) = tauri::async_runtime::spawn_blocking(move || {
+        let session_id = PTY_REGISTRY.create_session(options)?;
         let reader = PTY_REGISTRY.take_reader(&session_id)?;
         Ok::<_, Error>((session_id, reader))
     })
@@ -144,18 +547,24 @@ async fn spawn_pty(state: State<'_, AppState>, app_handle: AppHandle) -> Result<
     .map_err(|err| err.to_string())?
     .map_err(|err| err.to_string())?;
 
-    state
+    let spill = match scrollback.disk_bytes() {
+        Some(capacity) => open_disk_scrollback(&app_handle, &session_id, capacity).await,
+        None => None,
+    };
+    let snapshot = state
         .terminal_snapshots
-        .lock()
-        .await
-        .insert(session_id.clone(), TerminalSnapshot::default());
+        .insert(&session_id, TerminalSnapshot::new(size, scrollback, spill));
+    let recorder = RECORDINGS.register(&session_id);
 
     let reader_task = spawn_terminal_reader(
         app_handle,
         session_id.clone(),
         reader,
-        state.terminal_snapshots.clone(),
-    );
+        snapshot,
+        recorder,
+        output,
+    )
+    .map_err(|err| err.to_string())?;
     state
         .readers
         .lock()
@@ -171,7 +580,17 @@ async fn write_to_pty(request: WriteRequest) -> Result<(), String> {
     let bytes = data.into_bytes();
 
     tauri::async_runtime::spawn_blocking(move || {
-        PTY_REGISTRY.with_session(&session_id, |session| session.write(&bytes))
+        // Input typed into a synced session is mirrored to the rest of its
+        // group; only a failure on the session itself is reported back.
+        let targets = PTY_REGISTRY.sync_targets(&session_id);
+        for (id, result) in PTY_REGISTRY.broadcast(&targets, &bytes) {
+            match result {
+                Ok(()) => RECORDINGS.input(&id, &bytes),
+                Err(err) if id == session_id => return Err(err),
+                Err(err) => eprintln!("failed to mirror input to PTY session {id}: {err:#}"),
+            }
+        }
+        Ok(())
     })
     .await
     .map_err(|err| err.to_string())?
@@ -180,146 +599,967 @@ async fn write_to_pty(request: WriteRequest) -> Result<(), String> {
     Ok(())
 }
 
+/// Sends the same input to several sessions at once, reporting each
+/// session's outcome separately.
 #[tauri::command]
-async fn resize_pty(request: ResizeRequest) -> Result<(), String> {
+async fn broadcast_to_ptys(request: BroadcastRequest) -> Result<Vec<BroadcastOutcome>, String> {
+    let BroadcastRequest { session_ids, data } = request;
+    if session_ids.is_empty() {
+        return Err("no sessions to broadcast to".into());
+    }
+    let bytes = data.into_bytes();
+
     tauri::async_runtime::spawn_blocking(move || {
-        PTY_REGISTRY.with_session(&request.session_id, |session| {
-            let size = PtySize {
-                cols: request.cols,
-                rows: request.rows,
-                pixel_width: request.pixel_width.unwrap_or_default(),
-                pixel_height: request.pixel_height.unwrap_or_default(),
-            };
-            session.resize(size)
-        })
+        PTY_REGISTRY
+            .broadcast(&session_ids, &bytes)
+            .into_iter()
+            .map(|(session_id, result)| {
+                if result.is_ok() {
+                    RECORDINGS.input(&session_id, &bytes);
+                }
+                BroadcastOutcome {
+                    session_id,
+                    error: result.err().map(|err| format!("{err:#}")),
+                }
+            })
+            .collect()
+    })
+    .await
+    .map_err(|err| err.to_string())
+}
+
+/// Creates, replaces or (with no sessions) removes a sync input group.
+#[tauri::command]
+async fn set_sync_group(request: SyncGroupRequest) -> Result<Vec<SyncGroup>, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        PTY_REGISTRY.set_sync_group(&request.name, request.session_ids)?;
+        Ok::<_, Error>(PTY_REGISTRY.list_sync_groups())
+    })
+    .await
+    .map_err(|err| err.to_string())?
+    .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn list_sync_groups() -> Result<Vec<SyncGroup>, String> {
+    tauri::async_runtime::spawn_blocking(|| PTY_REGISTRY.list_sync_groups())
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn resize_pty(state: State<'_, AppState>, request: ResizeRequest) -> Result<(), String> {
+    let size = PtySize {
+        cols: request.cols,
+        rows: request.rows,
+        pixel_width: request.pixel_width.unwrap_or_default(),
+        pixel_height: request.pixel_height.unwrap_or_default(),
+    };
+    let session_id = request.session_id.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        PTY_REGISTRY.with_session(&session_id, |session| session.resize(size))
     })
     .await
     .map_err(|err| err.to_string())?
     .map_err(|err| err.to_string())?;
 
+    if let Some(snapshot) = state.terminal_snapshots.get(&request.session_id) {
+        snapshot.lock().resize(size.cols, size.rows);
+    }
+    RECORDINGS.resize(&request.session_id, size.cols, size.rows);
+
     Ok(())
 }
 
 #[tauri::command]
-async fn ask_ollama(app_handle: AppHandle, request: AskOllamaRequest) -> Result<(), String> {
-    let client = HTTP_CLIENT.clone();
+async fn kill_pty(
+    state: State<'_, AppState>,
+    app_handle: AppHandle,
+    request: KillRequest,
+) -> Result<PtyExitStatus, String> {
+    let KillRequest { session_id, signal } = request;
+    let signal = signal.unwrap_or_default();
+
+    let status = if PTY_REGISTRY.remove_read_only(&session_id) {
+        // A replay: once stopped, its playback thread finishes and cleans up.
+        let _ = REPLAYS.control(&session_id, ReplayAction::Stop);
+        PtyExitStatus {
+            exit_code: 0,
+            signal: None,
+        }
+    } else {
+        let id = session_id.clone();
+        tauri::async_runtime::spawn_blocking(move || {
+            PTY_REGISTRY.terminate_session(&id, signal, PTY_KILL_GRACE)
+        })
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())?
+    };
+
+    // The reader ends by itself once the PTY reports EOF, and a blocking task
+    // cannot be aborted. Waiting for it keeps the last output ahead of
+    // `terminal-closed`; a reader held open by a leftover child is left behind.
+    let reader = state.readers.lock().await.remove(&session_id);
+    if let Some(reader) = reader {
+        let _ = tokio::time::timeout(READER_SHUTDOWN_TIMEOUT, reader).await;
+    }
+    state.terminal_snapshots.remove(&session_id);
+    OUTPUT_FLOWS.remove(&session_id);
+    finish_recording(&session_id);
+
+    let _ = app_handle.emit(
+        "terminal-closed",
+        TerminalExitPayload {
+            session_id,
+            status: status.clone(),
+        },
+    );
+
+    Ok(status)
+}
+
+#[tauri::command]
+async fn start_recording(
+    app_handle: AppHandle,
+    request: StartRecordingRequest,
+) -> Result<String, String> {
+    let StartRecordingRequest {
+        session_id,
+        path,
+        title,
+        record_input,
+    } = request;
+
+    let path = match path.map(|value| value.trim().to_string()) {
+        Some(path) if !path.is_empty() => PathBuf::from(path),
+        _ => {
+            let stamp = unix_millis() / 1000;
+            app_handle
+                .path()
+                .app_data_dir()
+                .map_err(|err| err.to_string())?
+                .join("recordings")
+                .join(format!("{session_id}-{stamp}.cast"))
+        }
+    };
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let header = PTY_REGISTRY.with_session(&session_id, |session| {
+            let info = session.info();
+            Ok(CastHeader {
+                cols: info.cols,
+                rows: info.rows,
+                title: title.or(Some(info.title)),
+                term: Some(session.term().to_string()),
+            })
+        })?;
+        let recorder = CastRecorder::create(&path, &header, record_input)?;
+        RECORDINGS.start(&session_id, recorder)?;
+        Ok::<_, Error>(path.display().to_string())
+    })
+    .await
+    .map_err(|err| err.to_string())?
+    .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn stop_recording(session_id: String) -> Result<RecordingSummary, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        RECORDINGS
+            .stop(&session_id)
+            .ok_or_else(|| anyhow::anyhow!("session {session_id} is not being recorded"))?
+    })
+    .await
+    .map_err(|err| err.to_string())?
+    .map_err(|err| err.to_string())
+}
+
+/// Reports that the frontend has rendered `bytes` of a session's output.
+#[tauri::command]
+async fn ack_terminal_output(session_id: String, bytes: u64) -> Result<(), String> {
+    if let Some(flow) = OUTPUT_FLOWS.get(&session_id) {
+        flow.ack(bytes);
+    }
+    Ok(())
+}
+
+/// Plays a `.cast` file into a read-only pseudo-session, which is listed,
+/// focused and closed with `kill_pty` like any other session. Output arrives
+/// on `terminal-output` like a live PTY and feeds a snapshot, so context,
+/// search and export work on the replay too.
+#[tauri::command]
+async fn replay_cast(
+    state: State<'_, AppState>,
+    app_handle: AppHandle,
+    path: String,
+    speed: Option<f64>,
+) -> Result<ReplayInfo, String> {
+    let cast = tauri::async_runtime::spawn_blocking(move || CastFile::open(Path::new(&path)))
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())?;
+
+    let (session_id, control) = REPLAYS.register(cast.duration_ms(), speed.unwrap_or(1.0));
+    let size = initial_size(Some(cast.cols), Some(cast.rows));
+    let scrollback = ScrollbackConfig::default();
+    let snapshot = state
+        .terminal_snapshots
+        .insert(&session_id, TerminalSnapshot::new(size, &scrollback, None));
+
+    PTY_REGISTRY.add_read_only(SessionInfo {
+        id: session_id.clone(),
+        title: cast.title.clone().unwrap_or_else(|| "Replay".to_string()),
+        pid: None,
+        cwd: None,
+        foreground: None,
+        created_at: unix_millis(),
+        alive: true,
+        cols: size.cols,
+        rows: size.rows,
+        read_only: true,
+    });
+
+    let info = ReplayInfo {
+        session_id: session_id.clone(),
+        title: cast.title.clone(),
+        cols: cast.cols,
+        rows: cast.rows,
+        duration_ms: cast.duration_ms(),
+    };
+
+    let snapshots = state.terminal_snapshots.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        replay::run(&control, &cast, |event| match event {
+            ReplayEvent::Output(data) => {
+                snapshot.lock().append(data.as_bytes());
+                let _ = app_handle.emit(
+                    "terminal-output",
+                    TerminalOutputPayload::text(&session_id, data.to_string()),
+                );
+            }
+            ReplayEvent::Resize { cols, rows } => {
+                snapshot.lock().resize(cols, rows);
+                PTY_REGISTRY.resize_read_only(&session_id, cols, rows);
+                let _ = app_handle.emit(
+                    "terminal-resize",
+                    TerminalResizePayload {
+                        session_id: session_id.clone(),
+                        cols,
+                        rows,
+                    },
+                );
+            }
+            ReplayEvent::Reset => {
+                *snapshot.lock() = TerminalSnapshot::new(size, &scrollback, None);
+                let _ = app_handle.emit(
+                    "terminal-output",
+                    TerminalOutputPayload::text(&session_id, "\x1bc".to_string()),
+                );
+                let _ = app_handle.emit(
+                    "terminal-resize",
+                    TerminalResizePayload {
+                        session_id: session_id.clone(),
+                        cols: size.cols,
+                        rows: size.rows,
+                    },
+                );
+            }
+            ReplayEvent::Status(status) => {
+                let _ = app_handle.emit(
+                    "replay-state",
+                    ReplayStatePayload {
+                        session_id: session_id.clone(),
+                        status,
+                    },
+                );
+            }
+        });
+
+        REPLAYS.remove(&session_id);
+        PTY_REGISTRY.remove_read_only(&session_id);
+        snapshots.remove(&session_id);
+    });
+
+    Ok(info)
+}
+
+/// Pauses, resumes, seeks, changes the speed of, or stops a replay.
+#[tauri::command]
+async fn control_replay(request: ReplayControlRequest) -> Result<ReplayStatus, String> {
+    REPLAYS
+        .control(&request.session_id, request.action)
+        .map_err(|err| err.to_string())
+}
+
+/// Closes the session's recording, if any, and drops its recorder slot once
+/// its output has ended.
+fn finish_recording(session_id: &str) {
+    if let Some(Err(err)) = RECORDINGS.remove(session_id) {
+        eprintln!("failed to finish recording of session {session_id}: {err:#}");
+    }
+}
+
+#[tauri::command]
+async fn list_sessions() -> Result<Vec<SessionInfo>, String> {
+    tauri::async_runtime::spawn_blocking(|| PTY_REGISTRY.list_sessions())
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn rename_session(request: RenameRequest) -> Result<SessionInfo, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        PTY_REGISTRY.with_session(&request.session_id, |session| {
+            session.set_title(&request.title)?;
+            Ok(session.info())
+        })
+    })
+    .await
+    .map_err(|err| err.to_string())?
+    .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn focus_session(session_id: String) -> Result<(), String> {
+    tauri::async_runtime::spawn_blocking(move || PTY_REGISTRY.focus_session(&session_id))
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())
+}
+
+/// Streams a reply on `ollama-chunk` and returns the conversation id. Earlier
+/// turns of the conversation are sent along, trimmed to fit the context
+/// window; terminal context is only attached to the new prompt.
+#[tauri::command]
+async fn ask_ollama(
+    state: State<'_, AppState>,
+    app_handle: AppHandle,
+    request: AskOllamaRequest,
+) -> Result<String, String> {
     let AskOllamaRequest {
         prompt,
         model,
         system_prompt,
         persona_prompt,
         terminal_context,
+        conversation_id,
+        context_window,
+        persona,
+        request_id,
     } = request;
     let model = model.unwrap_or_else(|| "llama3".to_string());
+    let conversation_id = conversation_id
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let request_id = request_id
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
+    // Registered before anything is awaited, so Stop works from the moment
+    // the prompt is sent.
+    let (abort, registration) = AbortHandle::new_pair();
+    {
+        let mut streams = state.ollama_streams.lock().await;
+        if streams.contains_key(&request_id) {
+            return Err(format!("request {request_id} is already streaming"));
+        }
+        streams.insert(request_id.clone(), abort);
+    }
+
+    let context_tokens = context_window
+        .unwrap_or(DEFAULT_CONTEXT_TOKENS)
+        .clamp(CONTEXT_TOKENS_MIN, CONTEXT_TOKENS_MAX);
 
-    let mut messages = Vec::new();
+    let mut system = Vec::new();
 
     if let Some(system_prompt) = system_prompt
         .map(|value| value.trim().to_string())
         .filter(|value| !value.is_empty())
     {
-        messages.push(json!({
-            "role": "system",
-            "content": system_prompt,
-        }));
+        system.push(ChatMessage::new(ChatRole::System, system_prompt));
     }
 
     if let Some(persona_prompt) = persona_prompt
         .map(|value| value.trim().to_string())
         .filter(|value| !value.is_empty())
     {
-        messages.push(json!({
-            "role": "system",
-            "content": persona_prompt,
-        }));
+        system.push(ChatMessage::new(ChatRole::System, persona_prompt));
     }
 
-    let user_prompt = if let Some(context) = terminal_context
+    let terminal_context = terminal_context
         .map(|value| value.trim().to_string())
-        .filter(|value| !value.is_empty())
-    {
+        .filter(|value| !value.is_empty());
+    let user_prompt = if let Some(context) = &terminal_context {
         format!(
             "Recent terminal output:\n{}\n\nUser request:\n{}",
             context, prompt
         )
     } else {
-        prompt
+        prompt.clone()
     };
+    let user_message = ChatMessage::new(ChatRole::User, user_prompt);
 
-    messages.push(json!({
-        "role": "user",
-        "content": user_prompt,
-    }));
+    let mut stream = OllamaStream {
+        request_id: request_id.clone(),
+        conversation_id: conversation_id.clone(),
+        ..OllamaStream::default()
+    };
+    let reply = async {
+        let mut history = CONVERSATIONS.history(&conversation_id);
+        if history.is_empty() {
+            // After a restart the conversation may only exist on disk.
+            history = saved_conversation(&conversation_id).await;
+            CONVERSATIONS.restore(&conversation_id, Some(&model), history.clone());
+        }
+        let mut fixed = system.clone();
+        fixed.push(user_message.clone());
+        let budget = conversation::history_budget(context_tokens, &fixed);
+        let earlier = conversation::fit_history(&history, budget);
+
+        let messages: Vec<serde_json::Value> = system
+            .iter()
+            .chain(earlier)
+            .chain([&user_message])
+            .map(|message| json!({ "role": message.role, "content": message.content }))
+            .collect();
+
+        let mut body = json!({
+            "model": model,
+            "messages": messages,
+            "stream": true
+        });
+        if context_window.is_some() {
+            body["options"] = json!({ "num_ctx": context_tokens });
+        }
 
-    let body = json!({
-        "model": model,
-        "messages": messages,
-        "stream": true
-    });
+        stream_ollama_reply(&app_handle, &body, &mut stream).await
+    };
+    let outcome = Abortable::new(reply, registration).await;
+    state.ollama_streams.lock().await.remove(&request_id);
 
-    let response = client
-        .post("http://127.0.0.1:11434/api/chat")
-        .json(&body)
-        .send()
-        .await
-        .map_err(|err| err.to_string())?;
+    let cancelled = match outcome {
+        Ok(result) => {
+            result?;
+            false
+        }
+        Err(_) => {
+            let mut payload = stream.payload(None, true, None);
+            payload.cancelled = true;
+            emit_ollama_chunk(&app_handle, payload);
+            true
+        }
+    };
 
-    if !response.status().is_success() {
-        let status = response.status();
-        let detail = response.text().await.unwrap_or_default();
-        let message = format!("Ollama responded with {}: {}", status, detail);
-        emit_ollama_chunk(
-            &app_handle,
-            OllamaChunkPayload {
-                content: None,
-                done: true,
-                error: Some(message.clone()),
+    // Finished exchanges are remembered, and so are cancelled ones for the
+    // part the user already saw.
+    let complete = stream.done && !stream.failed;
+    if complete || (cancelled && !stream.content.is_empty()) {
+        CONVERSATIONS.record_turn(&conversation_id, &model, &prompt, &stream.content);
+        save_chat_turn(
+            NewChatMessage {
+                conversation_id: conversation_id.clone(),
+                role: ChatRole::User,
+                content: prompt,
+                model: Some(model.clone()),
+                persona: persona.clone(),
+                terminal_context,
             },
-        );
-        return Err(message);
+            NewChatMessage {
+                conversation_id: conversation_id.clone(),
+                role: ChatRole::Assistant,
+                content: stream.content,
+                model: Some(model),
+                persona,
+                terminal_context: None,
+            },
+        )
+        .await;
     }
 
-    let mut stream = response.bytes_stream();
-    let mut buffer: Vec<u8> = Vec::new();
+    Ok(conversation_id)
+}
+
+/// Sends `body` to Ollama's chat endpoint and relays the reply on
+/// `ollama-chunk` as it streams in. The reply may take as long as it needs,
+/// but gives up if the first chunk or the next one is too long in coming.
+async fn stream_ollama_reply(
+    app_handle: &AppHandle,
+    body: &serde_json::Value,
+    stream: &mut OllamaStream,
+) -> Result<(), String> {
+    let ollama = ollama::client();
+    let timeouts = *ollama.timeouts();
+
+    let first_chunk = async {
+        let response = ollama
+            .stream_chat(body)
+            .send()
+            .await
+            .map_err(|err| err.to_string())?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let detail = response.text().await.unwrap_or_default();
+            let message = format!("Ollama responded with {}: {}", status, detail);
+            return Err(fail_ollama_stream(app_handle, stream, message));
+        }
 
-    while let Some(chunk) = stream.next().await {
+        let mut chunks = response.bytes_stream();
+        let first = chunks.next().await;
+        Ok((chunks, first))
+    };
+    let (mut chunks, mut next) =
+        match tokio::time::timeout(timeouts.first_token(), first_chunk).await {
+            Ok(result) => result?,
+            Err(_) => {
+                let message = format!(
+                    "Ollama did not start replying within {} seconds",
+                    timeouts.first_token_secs
+                );
+                return Err(fail_ollama_stream(app_handle, stream, message));
+            }
+        };
+
+    let mut buffer: Vec<u8> = Vec::new();
+    while let Some(chunk) = next {
         let data = chunk.map_err(|err| err.to_string())?;
         buffer.extend_from_slice(&data);
-        process_ollama_buffer(&app_handle, &mut buffer)?;
+        process_ollama_buffer(app_handle, &mut buffer, stream)?;
+
+        next = match tokio::time::timeout(timeouts.idle(), chunks.next()).await {
+            Ok(next) => next,
+            Err(_) => {
+                let message = format!("Ollama stopped replying for {} seconds", timeouts.idle_secs);
+                return Err(fail_ollama_stream(app_handle, stream, message));
+            }
+        };
     }
 
     if !buffer.is_empty() {
         buffer.push(b'\n');
-        process_ollama_buffer(&app_handle, &mut buffer)?;
+        process_ollama_buffer(app_handle, &mut buffer, stream)?;
+    }
+
+    Ok(())
+}
+
+/// Ends the stream on the frontend with `message` and hands it back for the
+/// command's error.
+fn fail_ollama_stream(app_handle: &AppHandle, stream: &OllamaStream, message: String) -> String {
+    emit_ollama_chunk(
+        app_handle,
+        stream.payload(None, true, Some(message.clone())),
+    );
+    message
+}
+
+/// Stops an `ask_ollama` stream and closes its HTTP connection. Returns
+/// whether the request was still running.
+#[tauri::command]
+async fn cancel_ollama(state: State<'_, AppState>, request_id: String) -> Result<bool, String> {
+    match state.ollama_streams.lock().await.remove(&request_id) {
+        Some(abort) => {
+            abort.abort();
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// Saved conversations, including ones from earlier runs. Only the ones in
+/// memory are listed when the chat history is unavailable.
+#[tauri::command]
+async fn list_conversations() -> Result<Vec<ConversationSummary>, String> {
+    let Some(store) = CHAT_HISTORY.get() else {
+        return Ok(CONVERSATIONS.list());
+    };
+    tauri::async_runtime::spawn_blocking(move || store.conversations())
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn load_conversation(conversation_id: String) -> Result<Conversation, String> {
+    if let Ok(conversation) = CONVERSATIONS.load(&conversation_id) {
+        return Ok(conversation);
     }
+    let messages = saved_conversation(&conversation_id).await;
+    CONVERSATIONS.restore(&conversation_id, None, messages);
+    CONVERSATIONS
+        .load(&conversation_id)
+        .map_err(|err| err.to_string())
+}
 
+/// Forgets a conversation, both in memory and in the chat history, so it
+/// cannot be restored from disk afterwards.
+#[tauri::command]
+async fn clear_conversation(conversation_id: String) -> Result<(), String> {
+    let in_memory = CONVERSATIONS.clear(&conversation_id).is_ok();
+    let saved = match CHAT_HISTORY.get() {
+        Some(store) => {
+            let id = conversation_id.clone();
+            tauri::async_runtime::spawn_blocking(move || store.delete_conversation(&id))
+                .await
+                .map_err(|err| err.to_string())?
+                .map_err(|err| err.to_string())?
+        }
+        None => 0,
+    };
+    if !in_memory && saved == 0 {
+        return Err(format!("conversation {conversation_id} not found"));
+    }
     Ok(())
 }
 
+#[tauri::command]
+async fn search_chat_history(
+    query: String,
+    limit: Option<usize>,
+) -> Result<Vec<ChatSearchHit>, String> {
+    let limit = limit.unwrap_or(50).clamp(1, CHAT_SEARCH_LIMIT_MAX);
+    let store = chat_history()?;
+    tauri::async_runtime::spawn_blocking(move || store.search(&query, limit))
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())
+}
+
+/// Exports one conversation, or the whole chat history, as JSON or Markdown.
+#[tauri::command]
+async fn export_chat_history(
+    path: String,
+    format: ChatExportFormat,
+    conversation_id: Option<String>,
+) -> Result<String, String> {
+    let path = PathBuf::from(path.trim());
+    if path.as_os_str().is_empty() {
+        return Err("export path cannot be empty".into());
+    }
+    let store = chat_history()?;
+    tauri::async_runtime::spawn_blocking(move || {
+        store
+            .export(&path, format, conversation_id.as_deref())
+            .map_err(|err| format!("failed to export to {}: {err:#}", path.display()))?;
+        Ok(path.display().to_string())
+    })
+    .await
+    .map_err(|err| err.to_string())?
+}
+
+/// Deletes a message, a conversation, or with `all` the entire chat history.
+/// Returns the number of messages removed.
+#[tauri::command]
+async fn delete_chat_history(request: DeleteChatHistoryRequest) -> Result<usize, String> {
+    let store = chat_history()?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let DeleteChatHistoryRequest {
+            conversation_id,
+            message_id,
+            all,
+        } = request;
+        match (conversation_id, message_id) {
+            (_, Some(message_id)) => {
+                let Some(conversation_id) = store.delete_message(message_id)? else {
+                    return Ok(0);
+                };
+                // Reloaded from disk on next use, without the deleted message.
+                let _ = CONVERSATIONS.clear(&conversation_id);
+                Ok(1)
+            }
+            (Some(conversation_id), None) => {
+                let _ = CONVERSATIONS.clear(&conversation_id);
+                store.delete_conversation(&conversation_id)
+            }
+            (None, None) if all => {
+                CONVERSATIONS.clear_all();
+                store.delete_all()
+            }
+            (None, None) => {
+                anyhow::bail!("pass a conversation_id, a message_id, or all: true")
+            }
+        }
+    })
+    .await
+    .map_err(|err| err.to_string())?
+    .map_err(|err| err.to_string())
+}
+
+fn chat_history() -> Result<&'static ChatHistoryStore, String> {
+    CHAT_HISTORY
+        .get()
+        .ok_or_else(|| "chat history is not available".to_string())
+}
+
+/// Saves an answered prompt to the chat history. Failures are logged rather
+/// than failing the request.
+async fn save_chat_turn(prompt: NewChatMessage, reply: NewChatMessage) {
+    let Some(store) = CHAT_HISTORY.get() else {
+        return;
+    };
+    let result = tauri::async_runtime::spawn_blocking(move || store.record_turn(&prompt, &reply))
+        .await
+        .map_err(Error::from)
+        .and_then(|result| result);
+    if let Err(err) = result {
+        eprintln!("failed to save chat messages: {err:#}");
+    }
+}
+
+/// User and assistant turns of a conversation saved in the chat history.
+async fn saved_conversation(conversation_id: &str) -> Vec<ChatMessage> {
+    let Some(store) = CHAT_HISTORY.get() else {
+        return Vec::new();
+    };
+    let id = conversation_id.to_string();
+    let result = tauri::async_runtime::spawn_blocking(move || store.conversation(&id))
+        .await
+        .map_err(Error::from)
+        .and_then(|result| result);
+    match result {
+        Ok(messages) => answered_turns(messages),
+        Err(err) => {
+            eprintln!("failed to load conversation {conversation_id}: {err:#}");
+            Vec::new()
+        }
+    }
+}
+
+/// User and assistant turns with any prompt that never got a reply left out,
+/// as older versions saved prompts before sending them.
+fn answered_turns(messages: Vec<StoredChatMessage>) -> Vec<ChatMessage> {
+    let mut turns: Vec<ChatMessage> = Vec::with_capacity(messages.len());
+    for message in messages {
+        match message.role {
+            ChatRole::System => continue,
+            ChatRole::User => {
+                if turns.last().is_some_and(|last| last.role == ChatRole::User) {
+                    turns.pop();
+                }
+            }
+            ChatRole::Assistant => {}
+        }
+        turns.push(ChatMessage::from(message));
+    }
+    if turns.last().is_some_and(|last| last.role == ChatRole::User) {
+        turns.pop();
+    }
+    turns
+}
+
+fn session_snapshot(state: &AppState, session_id: &str) -> Result<SnapshotHandle, String> {
+    state
+        .terminal_snapshots
+        .get(session_id)
+        .ok_or_else(|| format!("terminal session {session_id} not found"))
+}
+
 #[tauri::command]
 async fn get_terminal_context(
     state: State<'_, AppState>,
-    session_id: String,
+    session_id: Option<String>,
     max_lines: Option<usize>,
+    offset: Option<usize>,
 ) -> Result<TerminalContextPayload, String> {
+    let session_id = session_id
+        .or_else(|| PTY_REGISTRY.focused_session())
+        .ok_or_else(|| "no terminal session is focused".to_string())?;
     let max_lines = max_lines.unwrap_or(200).min(TERMINAL_LINES_MAX).max(1);
-    let snapshots = state.terminal_snapshots.lock().await;
-    let snapshot = snapshots
-        .get(&session_id)
-        .ok_or_else(|| format!("terminal session {session_id} not found"))?;
+    let offset = offset.unwrap_or(0);
+    let snapshot = session_snapshot(&state, &session_id)?;
+    let (lines, total_lines) =
+        tauri::async_runtime::spawn_blocking(move || snapshot.lock().page(offset, max_lines))
+            .await
+            .map_err(|err| err.to_string())?
+            .map_err(|err| err.to_string())?;
 
     Ok(TerminalContextPayload {
         session_id,
-        last_lines: snapshot.last_lines(max_lines),
+        last_lines: lines.join("\n"),
+        offset: offset.min(total_lines),
+        total_lines,
+    })
+}
+
+#[tauri::command]
+async fn search_terminal(
+    state: State<'_, AppState>,
+    session_id: Option<String>,
+    pattern: String,
+    regex: Option<bool>,
+    case_sensitive: Option<bool>,
+    max_hits: Option<usize>,
+    context_lines: Option<usize>,
+) -> Result<TerminalSearchResults, String> {
+    let session_id = session_id
+        .or_else(|| PTY_REGISTRY.focused_session())
+        .ok_or_else(|| "no terminal session is focused".to_string())?;
+    if pattern.is_empty() {
+        return Err("search pattern cannot be empty".into());
+    }
+    let source = if regex.unwrap_or(false) {
+        pattern
+    } else {
+        regex::escape(&pattern)
+    };
+    let matcher = RegexBuilder::new(&source)
+        .case_insensitive(!case_sensitive.unwrap_or(false))
+        .build()
+        .map_err(|err| err.to_string())?;
+    let max_hits = max_hits.unwrap_or(100).clamp(1, TERMINAL_SEARCH_HITS_MAX);
+    let context_lines = context_lines.unwrap_or(2).min(TERMINAL_SEARCH_CONTEXT_MAX);
+
+    let snapshot = session_snapshot(&state, &session_id)?;
+    tauri::async_runtime::spawn_blocking(move || {
+        snapshot.lock().search(&matcher, max_hits, context_lines)
+    })
+    .await
+    .map_err(|err| err.to_string())?
+    .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn export_terminal(
+    state: State<'_, AppState>,
+    session_id: String,
+    format: ExportFormat,
+    path: String,
+) -> Result<String, String> {
+    let path = PathBuf::from(path.trim());
+    if path.as_os_str().is_empty() {
+        return Err("export path cannot be empty".into());
+    }
+
+    let snapshot = session_snapshot(&state, &session_id)?;
+    tauri::async_runtime::spawn_blocking(move || {
+        snapshot
+            .lock()
+            .export(format, &path)
+            .map_err(|err| format!("failed to export to {}: {err:#}", path.display()))?;
+        Ok(path.display().to_string())
+    })
+    .await
+    .map_err(|err| err.to_string())?
+}
+
+#[tauri::command]
+async fn get_command_history(
+    state: State<'_, AppState>,
+    session_id: Option<String>,
+    limit: Option<usize>,
+) -> Result<Vec<ShellCommand>, String> {
+    let limit = limit.unwrap_or(50).clamp(1, COMMAND_HISTORY_LIMIT_MAX);
+    let session_id = session_id
+        .or_else(|| PTY_REGISTRY.focused_session())
+        .ok_or_else(|| "no terminal session is focused".to_string())?;
+
+    if let Some(snapshot) = state.terminal_snapshots.get(&session_id) {
+        let mut commands: Vec<ShellCommand> = snapshot
+            .lock()
+            .shell()
+            .commands()
+            .rev()
+            .take(limit)
+            .map(|record| record.command.clone())
+            .collect();
+        commands.reverse();
+        return Ok(commands);
+    }
+
+    // Sessions from earlier runs are only available from the on-disk log.
+    let history = command_history()?;
+    tauri::async_runtime::spawn_blocking(move || history.for_session(&session_id, limit))
+        .await
+        .map_err(|err| err.to_string())?
+        .map(|entries| entries.into_iter().map(|entry| entry.record.command).collect())
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn get_command_output(
+    state: State<'_, AppState>,
+    session_id: String,
+    command_id: u64,
+) -> Result<RecordedCommand, String> {
+    if let Some(snapshot) = state.terminal_snapshots.get(&session_id) {
+        return snapshot
+            .lock()
+            .shell()
+            .command(command_id)
+            .cloned()
+            .ok_or_else(|| format!("command {command_id} not found in session {session_id}"));
+    }
+
+    let history = command_history()?;
+    let id = session_id.clone();
+    tauri::async_runtime::spawn_blocking(move || history.find(&id, command_id))
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())?
+        .map(|entry| entry.record)
+        .ok_or_else(|| format!("command {command_id} not found in session {session_id}"))
+}
+
+#[tauri::command]
+async fn search_command_history(
+    query: String,
+    limit: Option<usize>,
+) -> Result<Vec<HistoryEntry>, String> {
+    let limit = limit.unwrap_or(50).clamp(1, COMMAND_HISTORY_LIMIT_MAX);
+    let history = command_history()?;
+    tauri::async_runtime::spawn_blocking(move || history.search(query.trim(), limit))
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())
+}
+
+fn command_history() -> Result<&'static CommandHistoryStore, String> {
+    COMMAND_HISTORY
+        .get()
+        .ok_or_else(|| "command history is not available".to_string())
+}
+
+/// The current settings with the bearer token and header values left out.
+#[tauri::command]
+fn get_ollama_settings() -> OllamaSettingsView {
+    OllamaSettingsView::from(ollama::client().settings())
+}
+
+/// Saves the settings and applies them to every later Ollama request.
+/// Settings that cannot produce a working client are rejected unsaved.
+#[tauri::command]
+async fn save_ollama_settings(
+    app_handle: AppHandle,
+    settings: OllamaSettingsUpdate,
+) -> Result<OllamaSettingsView, String> {
+    let store = ollama_settings_store(&app_handle)?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let settings = settings.apply(ollama::client().settings())?;
+        let client = OllamaClient::new(settings.clone())?;
+        store.save(&settings)?;
+        ollama::install(client);
+        Ok::<_, Error>(OllamaSettingsView::from(&settings))
     })
+    .await
+    .map_err(|err| err.to_string())?
+    .map_err(|err| err.to_string())
+}
+
+fn ollama_settings_store(app_handle: &AppHandle) -> Result<OllamaSettingsStore, String> {
+    let config_dir = app_handle
+        .path()
+        .app_config_dir()
+        .map_err(|err| err.to_string())?;
+    Ok(OllamaSettingsStore::new(&config_dir))
 }
 
 #[tauri::command]
 async fn check_ollama() -> Result<bool, String> {
-    let response = HTTP_CLIENT
-        .get("http://127.0.0.1:11434/api/tags")
-        .send()
-        .await;
+    let ollama = ollama::client();
+    let response = ollama.tags().send().await;
 
     match response {
         Ok(res) => Ok(res.status().is_success()),
@@ -334,15 +1574,29 @@ struct SystemContext {
     username: Option<String>,
     local_ip: Option<String>,
     git_branch: Option<String>,
+    git_dirty: bool,
+    git_ahead: Option<u32>,
+    git_behind: Option<u32>,
+    git_stash_count: u32,
+    git_commit_subject: Option<String>,
     cwd: Option<String>,
     shell: Option<String>,
+    foreground_process: Option<String>,
+    foreground_pid: Option<u32>,
     ollama_online: bool,
 }
 
 #[tauri::command]
-async fn get_system_context(_session_id: Option<String>) -> Result<SystemContext, String> {
+async fn get_system_context(session_id: Option<String>) -> Result<SystemContext, String> {
     use std::env;
-    use std::process::Command;
+
+    let session = tauri::async_runtime::spawn_blocking(move || {
+        session_id
+            .or_else(|| PTY_REGISTRY.focused_session())
+            .and_then(|id| PTY_REGISTRY.with_session(&id, |session| Ok(session.info())).ok())
+    })
+    .await
+    .map_err(|err| err.to_string())?;
 
     // Get hostname
     let hostname = hostname::get()
@@ -359,54 +1613,34 @@ async fn get_system_context(_session_id: Option<String>) -> Result<SystemContext
         .ok()
         .and_then(|s| s.split('/').last().map(String::from));
 
-    // Get current working directory
-    let cwd = env::current_dir()
-        .ok()
-        .and_then(|p| p.to_str().map(String::from));
-
-    // Get git branch - try from the executable's directory first (likely the project)
-    let exe_dir = env::current_exe()
-        .ok()
-        .and_then(|p| p.parent().map(|d| d.to_path_buf()))
-        // Go up from target/debug to project root
-        .and_then(|p| p.parent().map(|d| d.to_path_buf()))
-        .and_then(|p| p.parent().map(|d| d.to_path_buf()));
-
-    let git_branch = exe_dir.as_ref()
-        .and_then(|dir| {
-            Command::new("git")
-                .args(["rev-parse", "--abbrev-ref", "HEAD"])
-                .current_dir(dir)
-                .stderr(std::process::Stdio::null())
-                .output()
-                .ok()
-                .filter(|output| output.status.success())
-                .and_then(|output| String::from_utf8(output.stdout).ok())
-                .map(|s| s.trim().to_string())
-                .filter(|s| !s.is_empty())
-        })
+    // Get current working directory, preferring the shell's over the app's
+    let cwd = session
+        .as_ref()
+        .and_then(|info| info.cwd.clone())
         .or_else(|| {
-            // Fallback: try from cwd
-            cwd.as_ref().and_then(|dir| {
-                Command::new("git")
-                    .args(["rev-parse", "--abbrev-ref", "HEAD"])
-                    .current_dir(dir)
-                    .stderr(std::process::Stdio::null())
-                    .output()
-                    .ok()
-                    .filter(|output| output.status.success())
-                    .and_then(|output| String::from_utf8(output.stdout).ok())
-                    .map(|s| s.trim().to_string())
-                    .filter(|s| !s.is_empty())
-            })
+            env::current_dir()
+                .ok()
+                .and_then(|p| p.to_str().map(String::from))
         });
 
+    let foreground = session.and_then(|info| info.foreground);
+
+    // Get git status for the directory the shell is actually in
+    let git = match cwd.clone() {
+        Some(dir) => tauri::async_runtime::spawn_blocking(move || git::status(Path::new(&dir)))
+            .await
+            .map_err(|err| err.to_string())?,
+        None => None,
+    }
+    .unwrap_or_default();
+
     // Get local IP address
     let local_ip = get_local_ip();
 
     // Check if Ollama is online
-    let ollama_online = HTTP_CLIENT
-        .get("http://127.0.0.1:11434/api/tags")
+    let ollama = ollama::client();
+    let ollama_online = ollama
+        .tags()
         .send()
         .await
         .map(|res| res.status().is_success())
@@ -416,9 +1650,16 @@ async fn get_system_context(_session_id: Option<String>) -> Result<SystemContext
         hostname,
         username,
         local_ip,
-        git_branch,
+        git_branch: git.branch,
+        git_dirty: git.dirty,
+        git_ahead: git.ahead,
+        git_behind: git.behind,
+        git_stash_count: git.stash_count,
+        git_commit_subject: git.commit_subject,
         cwd,
         shell,
+        foreground_process: foreground.as_ref().and_then(|process| process.name.clone()),
+        foreground_pid: foreground.map(|process| process.pid),
         ollama_online,
     })
 }
@@ -436,11 +1677,8 @@ fn get_local_ip() -> Option<String> {
 
 #[tauri::command]
 async fn list_ollama_models() -> Result<Vec<String>, String> {
-    let response = HTTP_CLIENT
-        .get("http://127.0.0.1:11434/api/tags")
-        .send()
-        .await
-        .map_err(|err| err.to_string())?;
+    let ollama = ollama::client();
+    let response = ollama.tags().send().await.map_err(|err| err.to_string())?;
 
     if !response.status().is_success() {
         let status = response.status();
@@ -513,9 +1751,9 @@ async fn analyze_command(request: AnalyzeCommandRequest) -> Result<AnalyzeComman
         "stream": false
     });
 
-    let response = HTTP_CLIENT
-        .post("http://127.0.0.1:11434/api/chat")
-        .json(&body)
+    let ollama = ollama::client();
+    let response = ollama
+        .chat(&body)
         .send()
         .await
         .map_err(|err| err.to_string())?;
@@ -981,9 +2219,9 @@ async fn repair_preflight_report(
         "stream": false
     });
 
-    let response = HTTP_CLIENT
-        .post("http://127.0.0.1:11434/api/chat")
-        .json(&body)
+    let ollama = ollama::client();
+    let response = ollama
+        .chat(&body)
         .send()
         .await
         .map_err(|err| err.to_string())?;
@@ -1037,9 +2275,9 @@ async fn fallback_text_summary(
         "stream": false
     });
 
-    let response = HTTP_CLIENT
-        .post("http://127.0.0.1:11434/api/chat")
-        .json(&body)
+    let ollama = ollama::client();
+    let response = ollama
+        .chat(&body)
         .send()
         .await
         .map_err(|err| err.to_string())?;
@@ -1069,7 +2307,39 @@ async fn fallback_text_summary(
     Ok(sanitize_plain_text_assessment(&content))
 }
 
-fn process_ollama_buffer(app_handle: &AppHandle, buffer: &mut Vec<u8>) -> Result<(), String> {
+/// One `ask_ollama` stream and what has come back on it so far.
+#[derive(Default)]
+struct OllamaStream {
+    request_id: String,
+    conversation_id: String,
+    content: String,
+    done: bool,
+    failed: bool,
+}
+
+impl OllamaStream {
+    fn payload(
+        &self,
+        content: Option<String>,
+        done: bool,
+        error: Option<String>,
+    ) -> OllamaChunkPayload {
+        OllamaChunkPayload {
+            request_id: self.request_id.clone(),
+            conversation_id: self.conversation_id.clone(),
+            content,
+            done,
+            error,
+            cancelled: false,
+        }
+    }
+}
+
+fn process_ollama_buffer(
+    app_handle: &AppHandle,
+    buffer: &mut Vec<u8>,
+    stream: &mut OllamaStream,
+) -> Result<(), String> {
     loop {
         let Some(position) = buffer.iter().position(|b| *b == b'\n') else {
             break;
@@ -1085,46 +2355,37 @@ fn process_ollama_buffer(app_handle: &AppHandle, buffer: &mut Vec<u8>) -> Result
 
         let chunk: OllamaResponseChunk =
             serde_json::from_str(trimmed).map_err(|err| err.to_string())?;
-        handle_ollama_chunk(app_handle, chunk);
+        handle_ollama_chunk(app_handle, chunk, stream);
     }
 
     Ok(())
 }
 
-fn handle_ollama_chunk(app_handle: &AppHandle, chunk: OllamaResponseChunk) {
+fn handle_ollama_chunk(
+    app_handle: &AppHandle,
+    chunk: OllamaResponseChunk,
+    stream: &mut OllamaStream,
+) {
     if let Some(error) = chunk.error {
-        emit_ollama_chunk(
-            app_handle,
-            OllamaChunkPayload {
-                content: None,
-                done: true,
-                error: Some(error),
-            },
-        );
+        stream.failed = true;
+        emit_ollama_chunk(app_handle, stream.payload(None, true, Some(error)));
         return;
     }
 
+    let done = chunk.done.unwrap_or(false);
+    stream.done |= done;
+
     if let Some(message) = chunk.message {
+        stream.content.push_str(&message.content);
         emit_ollama_chunk(
             app_handle,
-            OllamaChunkPayload {
-                content: Some(message.content),
-                done: chunk.done.unwrap_or(false),
-                error: None,
-            },
+            stream.payload(Some(message.content), done, None),
         );
         return;
     }
 
-    if chunk.done.unwrap_or(false) {
-        emit_ollama_chunk(
-            app_handle,
-            OllamaChunkPayload {
-                content: None,
-                done: true,
-                error: None,
-            },
-        );
+    if done {
+        emit_ollama_chunk(app_handle, stream.payload(None, true, None));
     }
 }
 
@@ -1135,39 +2396,112 @@ fn emit_ollama_chunk(app_handle: &AppHandle, payload: OllamaChunkPayload) {
 fn spawn_terminal_reader(
     app_handle: AppHandle,
     session_id: String,
-    mut reader: Box<dyn Read + Send>,
-    snapshots: Arc<Mutex<HashMap<String, TerminalSnapshot>>>,
-) -> ReaderHandle {
-    tauri::async_runtime::spawn_blocking(move || {
-        let mut buf = [0_u8; 4096];
-        loop {
-            match reader.read(&mut buf) {
-                Ok(0) => break,
-                Ok(len) => {
-                    let chunk = String::from_utf8_lossy(&buf[..len]).to_string();
+    reader: Box<dyn Read + Send>,
+    snapshot: SnapshotHandle,
+    recorder: RecorderHandle,
+    output: OutputOptions,
+) -> Result<ReaderHandle, Error> {
+    let reads = output::spawn_reader(&session_id, reader)?;
+    let flow = output
+        .flow_control
+        .then(|| OUTPUT_FLOWS.register(&session_id));
+
+    Ok(tauri::async_runtime::spawn_blocking(move || {
+        let mut decoder = Utf8Decoder::default();
+        // Each batch takes the session's own snapshot and recorder locks and
+        // crosses the IPC bridge once, however many reads it coalesced.
+        while let Some(batch) = output::next_batch(&reads) {
+            let bytes = batch.bytes.as_slice();
+            if !bytes.is_empty() {
+                recorder.output(bytes);
+                let finished = snapshot.lock().append(bytes);
+
+                let data = output.encoding.encode(&mut decoder, bytes);
+                let delivery = if data.is_empty() {
+                    Delivery::Drop
+                } else if let Some(flow) = &flow {
+                    flow.admit(bytes.len(), |stats| {
+                        let _ = app_handle.emit(
+                            "terminal-flow",
+                            TerminalFlowPayload {
+                                session_id: session_id.clone(),
+                                stats: stats.clone(),
+                            },
+                        );
+                    })
+                } else {
+                    Delivery::Send
+                };
+                let data = match delivery {
+                    Delivery::Send => Some(data),
+                    Delivery::Drop => None,
+                    // The batch is already in the snapshot, so the redraw
+                    // covers it along with everything that was dropped.
+                    Delivery::Redraw => {
+                        let screen = snapshot.lock().redraw();
+                        Some(match output.encoding {
+                            OutputEncoding::Utf8 => screen,
+                            OutputEncoding::Base64 => BASE64_STANDARD.encode(screen),
+                        })
+                    }
+                };
+                if let Some(data) = data {
                     let payload = TerminalOutputPayload {
                         session_id: session_id.clone(),
-                        data: chunk.clone(),
+                        data,
+                        encoding: output.encoding,
+                        bytes: bytes.len(),
                     };
-                    tauri::async_runtime::block_on(async {
-                        let mut guard = snapshots.lock().await;
-                        if let Some(snapshot) = guard.get_mut(&session_id) {
-                            snapshot.append(&chunk);
-                        }
-                    });
                     let _ = app_handle.emit("terminal-output", payload);
                 }
-                Err(err) => {
-                    let payload = TerminalOutputPayload {
-                        session_id: session_id.clone(),
-                        data: format!("[PTY ERROR] {err}"),
-                    };
+
+                for record in finished {
+                    if let Some(history) = COMMAND_HISTORY.get() {
+                        if let Err(err) = history.append(&session_id, &record) {
+                            eprintln!("failed to record command history: {err:#}");
+                        }
+                    }
+                    let _ = app_handle.emit(
+                        "terminal-command",
+                        TerminalCommandPayload {
+                            session_id: session_id.clone(),
+                            command: record.command,
+                        },
+                    );
+                }
+            }
+
+            match batch.end {
+                Some(Ok(())) => break,
+                Some(Err(err)) => {
+                    let payload =
+                        TerminalOutputPayload::text(&session_id, format!("[PTY ERROR] {err}"));
                     let _ = app_handle.emit("terminal-output", payload);
                     break;
                 }
+                None => {}
             }
         }
-    })
+
+        let tail = decoder.finish();
+        if !tail.is_empty() {
+            let _ = app_handle.emit(
+                "terminal-output",
+                TerminalOutputPayload::text(&session_id, tail),
+            );
+        }
+
+        OUTPUT_FLOWS.remove(&session_id);
+        finish_recording(&session_id);
+
+        // A missing session means kill_pty already tore it down and reported it.
+        if let Ok(status) = PTY_REGISTRY.reap_session(&session_id, PTY_KILL_GRACE) {
+            let _ = app_handle.emit(
+                "terminal-exit",
+                TerminalExitPayload { session_id, status },
+            );
+        }
+    }))
 }
 
 fn suspicion_score(command: &str) -> i32 {
@@ -1283,6 +2617,33 @@ pub fn run() {
         .manage(AppState::default())
         .plugin(tauri_plugin_opener::init())
         .setup(|app| {
+            match app.path().app_data_dir() {
+                Ok(data_dir) => {
+                    let _ = COMMAND_HISTORY.set(CommandHistoryStore::new(&data_dir));
+                }
+                Err(err) => eprintln!("command history disabled: {err}"),
+            }
+            match app.path().app_data_dir() {
+                Ok(data_dir) => match ChatHistoryStore::open(&data_dir) {
+                    Ok(store) => {
+                        let _ = CHAT_HISTORY.set(store);
+                    }
+                    Err(err) => eprintln!("chat history disabled: {err:#}"),
+                },
+                Err(err) => eprintln!("chat history disabled: {err}"),
+            }
+            match app.path().app_config_dir() {
+                Ok(config_dir) => {
+                    let loaded = OllamaSettingsStore::new(&config_dir)
+                        .load()
+                        .and_then(OllamaClient::new)
+                        .map(ollama::install);
+                    if let Err(err) = loaded {
+                        eprintln!("using default Ollama settings: {err:#}");
+                    }
+                }
+                Err(err) => eprintln!("using default Ollama settings: {err}"),
+            }
             if let Some(window) = app.get_webview_window("main") {
                 if let Some(icon) = app.default_window_icon().cloned() {
                     if let Err(err) = window.set_icon(icon) {
@@ -1294,15 +2655,90 @@ pub fn run() {
         })
         .invoke_handler(tauri::generate_handler![
             spawn_pty,
+            spawn_pty_with_profile,
+            list_profiles,
+            save_profile,
+            delete_profile,
+            get_shell_integration_script,
             write_to_pty,
+            broadcast_to_ptys,
+            set_sync_group,
+            list_sync_groups,
+            ack_terminal_output,
             resize_pty,
+            kill_pty,
+            start_recording,
+            stop_recording,
+            replay_cast,
+            control_replay,
+            list_sessions,
+            rename_session,
+            focus_session,
             ask_ollama,
+            cancel_ollama,
+            list_conversations,
+            load_conversation,
+            clear_conversation,
+            search_chat_history,
+            export_chat_history,
+            delete_chat_history,
+            get_ollama_settings,
+            save_ollama_settings,
             check_ollama,
             list_ollama_models,
             get_terminal_context,
+            search_terminal,
+            export_terminal,
+            get_command_history,
+            get_command_output,
+            search_command_history,
             get_system_context,
             analyze_command
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pseudo-random bytes, with invalid UTF-8 throughout.
+    fn arbitrary_bytes() -> Vec<u8> {
+        (0..4096_u32)
+            .map(|n| (n.wrapping_mul(2_654_435_761) >> 13) as u8)
+            .collect()
+    }
+
+    #[test]
+    fn base64_output_round_trips_arbitrary_bytes_in_any_chunking() {
+        let bytes = arbitrary_bytes();
+        for chunk_len in [1, 2, 3, 7, 64, 4096] {
+            let mut decoder = Utf8Decoder::default();
+            let mut decoded = Vec::new();
+            for chunk in bytes.chunks(chunk_len) {
+                let data = OutputEncoding::Base64.encode(&mut decoder, chunk);
+                decoded.extend(BASE64_STANDARD.decode(data).unwrap());
+            }
+            assert_eq!(decoded, bytes, "chunks of {chunk_len}");
+        }
+    }
+
+    #[test]
+    fn utf8_output_matches_lossy_decoding_of_the_whole_stream() {
+        let bytes = arbitrary_bytes();
+        for chunk_len in [1, 2, 3, 7, 64, 4096] {
+            let mut decoder = Utf8Decoder::default();
+            let mut text: String = bytes
+                .chunks(chunk_len)
+                .map(|chunk| OutputEncoding::Utf8.encode(&mut decoder, chunk))
+                .collect();
+            text.push_str(&decoder.finish());
+            assert_eq!(
+                text,
+                String::from_utf8_lossy(&bytes),
+                "chunks of {chunk_len}"
+            );
+        }
+    }
+}
@@ -0,0 +1,402 @@
+use std::{
+    collections::HashMap,
+    io::{self, Read},
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError, SyncSender},
+        Arc, Condvar, Mutex, MutexGuard,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+
+/// Output arriving within this window of the first byte is sent as one batch.
+pub const OUTPUT_BATCH_WINDOW: Duration = Duration::from_millis(8);
+/// A batch is sent early once it reaches this size.
+pub const OUTPUT_BATCH_BYTES: usize = 64 * 1024;
+const READ_CHUNK_BYTES: usize = 4096;
+/// Reads buffered between the PTY and the pump. When full the reader stops
+/// reading, the kernel PTY buffer fills up, and the child blocks on write.
+const OUTPUT_CHANNEL_CHUNKS: usize = 64;
+/// Undelivered bytes at which the pump stops sending to the frontend...
+const FLOW_HIGH_WATERMARK: u64 = 1024 * 1024;
+/// ...and the level it must drain back to before sending resumes.
+const FLOW_LOW_WATERMARK: u64 = 256 * 1024;
+/// How long the pump waits on a stalled frontend before it starts dropping
+/// output (still recorded in the snapshot) so the child is not blocked forever.
+const FLOW_STALL_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Flow-control state for sessions whose frontend acknowledges output.
+pub static OUTPUT_FLOWS: Lazy<FlowRegistry> = Lazy::new(FlowRegistry::default);
+
+pub enum ReadEvent {
+    Data(Vec<u8>),
+    Closed,
+    Failed(io::Error),
+}
+
+/// Output coalesced from one or more reads.
+#[derive(Default)]
+pub struct OutputBatch {
+    pub bytes: Vec<u8>,
+    /// Set when the stream ended after these bytes; `Some(Err)` for a read error.
+    pub end: Option<io::Result<()>>,
+}
+
+/// Moves PTY reads onto a dedicated thread feeding a bounded channel.
+pub fn spawn_reader(
+    session_id: &str,
+    mut reader: Box<dyn Read + Send>,
+) -> Result<Receiver<ReadEvent>> {
+    let (tx, rx) = mpsc::sync_channel(OUTPUT_CHANNEL_CHUNKS);
+    thread::Builder::new()
+        .name(format!("pty-reader-{session_id}"))
+        .spawn(move || read_loop(&mut reader, &tx))
+        .context("failed to start PTY reader thread")?;
+    Ok(rx)
+}
+
+fn read_loop(reader: &mut Box<dyn Read + Send>, tx: &SyncSender<ReadEvent>) {
+    let mut buf = [0_u8; READ_CHUNK_BYTES];
+    loop {
+        let event = match reader.read(&mut buf) {
+            Ok(0) => ReadEvent::Closed,
+            Ok(len) => ReadEvent::Data(buf[..len].to_vec()),
+            Err(err) if is_pty_eof(&err) => ReadEvent::Closed,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => ReadEvent::Failed(err),
+        };
+        let done = !matches!(event, ReadEvent::Data(_));
+        if tx.send(event).is_err() || done {
+            return;
+        }
+    }
+}
+
+/// Blocks for the next read, then gathers whatever else arrives within
+/// [`OUTPUT_BATCH_WINDOW`], up to [`OUTPUT_BATCH_BYTES`]. Returns `None` once
+/// the reader is gone and nothing is left.
+pub fn next_batch(rx: &Receiver<ReadEvent>) -> Option<OutputBatch> {
+    let mut batch = OutputBatch::default();
+    match rx.recv().ok()? {
+        ReadEvent::Data(bytes) => batch.bytes = bytes,
+        ReadEvent::Closed => {
+            batch.end = Some(Ok(()));
+            return Some(batch);
+        }
+        ReadEvent::Failed(err) => {
+            batch.end = Some(Err(err));
+            return Some(batch);
+        }
+    }
+
+    let deadline = Instant::now() + OUTPUT_BATCH_WINDOW;
+    while batch.bytes.len() < OUTPUT_BATCH_BYTES {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        match rx.recv_timeout(remaining) {
+            Ok(ReadEvent::Data(bytes)) => batch.bytes.extend_from_slice(&bytes),
+            Ok(ReadEvent::Closed) | Err(RecvTimeoutError::Disconnected) => {
+                batch.end = Some(Ok(()));
+                break;
+            }
+            Ok(ReadEvent::Failed(err)) => {
+                batch.end = Some(Err(err));
+                break;
+            }
+            Err(RecvTimeoutError::Timeout) => break,
+        }
+    }
+    Some(batch)
+}
+
+/// Linux reports EIO on the master once the child side of the PTY has closed.
+pub fn is_pty_eof(err: &io::Error) -> bool {
+    #[cfg(unix)]
+    {
+        err.raw_os_error() == Some(libc::EIO)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = err;
+        false
+    }
+}
+
+/// Delivery state reported to the frontend on `terminal-flow`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FlowStats {
+    pub unacked_bytes: u64,
+    /// The pump is waiting for the frontend to catch up.
+    pub throttled: bool,
+    /// The frontend stalled and output is being dropped until it catches up.
+    pub dropping: bool,
+    pub throttled_ms: u64,
+    pub dropped_bytes: u64,
+}
+
+/// What the pump should do with a batch, as decided by [`OutputFlow::admit`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    Send,
+    /// The frontend is stalled; the batch only goes to the snapshot.
+    Drop,
+    /// Dropping just ended. The frontend missed output, so it should get a
+    /// redraw of the current screen in place of the batch.
+    Redraw,
+}
+
+#[derive(Default)]
+struct FlowState {
+    stats: FlowStats,
+    closed: bool,
+}
+
+/// Source of the current time. Tests swap in one they can move forward.
+type Clock = Box<dyn Fn() -> Instant + Send + Sync>;
+
+/// Tracks bytes emitted to the frontend but not yet acknowledged.
+pub struct OutputFlow {
+    state: Mutex<FlowState>,
+    acked: Condvar,
+    clock: Clock,
+}
+
+impl Default for OutputFlow {
+    fn default() -> Self {
+        Self::with_clock(Box::new(Instant::now))
+    }
+}
+
+impl OutputFlow {
+    fn with_clock(clock: Clock) -> Self {
+        Self {
+            state: Mutex::default(),
+            acked: Condvar::new(),
+            clock,
+        }
+    }
+
+    /// Decides how `len` bytes are delivered, waiting while the frontend is
+    /// above the high watermark. `report` is called whenever the flow state
+    /// changes.
+    pub fn admit<F>(&self, len: usize, mut report: F) -> Delivery
+    where
+        F: FnMut(&FlowStats),
+    {
+        let len = len as u64;
+        let mut state = self.lock();
+
+        if state.stats.dropping {
+            if state.stats.unacked_bytes > FLOW_LOW_WATERMARK && !state.closed {
+                state.stats.dropped_bytes += len;
+                return Delivery::Drop;
+            }
+            state.stats.dropping = false;
+            report(&state.stats);
+            state.stats.unacked_bytes += len;
+            return Delivery::Redraw;
+        }
+
+        if state.stats.unacked_bytes > FLOW_HIGH_WATERMARK && !state.closed {
+            state.stats.throttled = true;
+            report(&state.stats);
+
+            let started = (self.clock)();
+            let elapsed = || (self.clock)().saturating_duration_since(started);
+            while state.stats.unacked_bytes > FLOW_LOW_WATERMARK && !state.closed {
+                let remaining = FLOW_STALL_TIMEOUT.saturating_sub(elapsed());
+                if remaining.is_zero() {
+                    state.stats.dropping = true;
+                    break;
+                }
+                state = self
+                    .acked
+                    .wait_timeout(state, remaining)
+                    .unwrap_or_else(|err| err.into_inner())
+                    .0;
+            }
+
+            state.stats.throttled = false;
+            state.stats.throttled_ms += elapsed().as_millis() as u64;
+            if state.stats.dropping {
+                state.stats.dropped_bytes += len;
+            }
+            report(&state.stats);
+            if state.stats.dropping {
+                return Delivery::Drop;
+            }
+        }
+
+        state.stats.unacked_bytes += len;
+        Delivery::Send
+    }
+
+    pub fn ack(&self, bytes: u64) {
+        let mut state = self.lock();
+        state.stats.unacked_bytes = state.stats.unacked_bytes.saturating_sub(bytes);
+        self.acked.notify_all();
+    }
+
+    /// Releases a pump blocked in [`OutputFlow::admit`].
+    fn close(&self) {
+        self.lock().closed = true;
+        self.acked.notify_all();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, FlowState> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+#[derive(Default)]
+pub struct FlowRegistry {
+    flows: Mutex<HashMap<String, Arc<OutputFlow>>>,
+}
+
+impl FlowRegistry {
+    pub fn register(&self, session_id: &str) -> Arc<OutputFlow> {
+        let flow = Arc::new(OutputFlow::default());
+        self.lock().insert(session_id.to_string(), flow.clone());
+        flow
+    }
+
+    pub fn get(&self, session_id: &str) -> Option<Arc<OutputFlow>> {
+        self.lock().get(session_id).cloned()
+    }
+
+    pub fn remove(&self, session_id: &str) {
+        if let Some(flow) = self.lock().remove(session_id) {
+            flow.close();
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, Arc<OutputFlow>>> {
+        self.flows.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+
+    /// Runs `admit`, returning its decision and every state it reported.
+    fn admit(flow: &OutputFlow, len: usize) -> (Delivery, Vec<FlowStats>) {
+        let mut reports = Vec::new();
+        let delivery = flow.admit(len, |stats| reports.push(stats.clone()));
+        (delivery, reports)
+    }
+
+    /// A clock that moves a whole stall timeout forward each time it is read,
+    /// so a throttled pump gives up without really waiting.
+    fn stalling_clock() -> Clock {
+        let start = Instant::now();
+        let reads = AtomicU32::new(0);
+        Box::new(move || start + FLOW_STALL_TIMEOUT * reads.fetch_add(1, Ordering::SeqCst))
+    }
+
+    /// Sends one batch that leaves the frontend over the high watermark.
+    fn fill_past_high_watermark(flow: &OutputFlow) {
+        let len = FLOW_HIGH_WATERMARK as usize + 1;
+        assert_eq!(admit(flow, len).0, Delivery::Send);
+    }
+
+    /// A flow whose frontend has stalled, already dropping output.
+    fn dropping_flow() -> OutputFlow {
+        let flow = OutputFlow::with_clock(stalling_clock());
+        fill_past_high_watermark(&flow);
+        let (delivery, reports) = admit(&flow, 10);
+        assert_eq!(delivery, Delivery::Drop);
+        assert!(reports[0].throttled);
+        assert!(reports.last().unwrap().dropping);
+        flow
+    }
+
+    #[test]
+    fn output_is_sent_until_the_high_watermark() {
+        let flow = OutputFlow::default();
+        for _ in 0..FLOW_HIGH_WATERMARK / 1024 {
+            let (delivery, reports) = admit(&flow, 1024);
+            assert_eq!(delivery, Delivery::Send);
+            assert!(reports.is_empty());
+        }
+        assert_eq!(flow.lock().stats.unacked_bytes, FLOW_HIGH_WATERMARK);
+    }
+
+    #[test]
+    fn stalled_frontend_starts_dropping_after_the_timeout() {
+        let flow = dropping_flow();
+        let stats = flow.lock().stats.clone();
+        assert!(stats.dropping);
+        assert!(!stats.throttled);
+        assert_eq!(stats.dropped_bytes, 10);
+        assert!(stats.throttled_ms >= FLOW_STALL_TIMEOUT.as_millis() as u64);
+        assert_eq!(stats.unacked_bytes, FLOW_HIGH_WATERMARK + 1);
+    }
+
+    #[test]
+    fn dropping_continues_until_the_low_watermark_then_redraws() {
+        let flow = dropping_flow();
+
+        // Below the high watermark is not enough to resume.
+        flow.ack(FLOW_HIGH_WATERMARK + 1 - (FLOW_LOW_WATERMARK + 1));
+        let (delivery, reports) = admit(&flow, 20);
+        assert_eq!(delivery, Delivery::Drop);
+        assert!(reports.is_empty());
+        assert_eq!(flow.lock().stats.dropped_bytes, 30);
+
+        flow.ack(1);
+        let (delivery, reports) = admit(&flow, 40);
+        assert_eq!(delivery, Delivery::Redraw);
+        assert_eq!(reports.len(), 1);
+        assert!(!reports[0].dropping);
+        assert_eq!(flow.lock().stats.unacked_bytes, FLOW_LOW_WATERMARK + 40);
+
+        assert_eq!(admit(&flow, 1).0, Delivery::Send);
+    }
+
+    #[test]
+    fn throttled_pump_waits_for_the_low_watermark() {
+        let flow = Arc::new(OutputFlow::default());
+        fill_past_high_watermark(&flow);
+
+        let acker = {
+            let flow = flow.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(20));
+                flow.ack(FLOW_HIGH_WATERMARK - FLOW_LOW_WATERMARK);
+                thread::sleep(Duration::from_millis(50));
+                flow.ack(1);
+            })
+        };
+        let started = Instant::now();
+        let (delivery, reports) = admit(&flow, 10);
+        acker.join().unwrap();
+
+        assert_eq!(delivery, Delivery::Send);
+        assert!(started.elapsed() >= Duration::from_millis(70));
+        assert_eq!(reports.len(), 2);
+        assert!(reports[0].throttled);
+        assert!(!reports[1].throttled && !reports[1].dropping);
+        assert_eq!(flow.lock().stats.unacked_bytes, FLOW_LOW_WATERMARK + 10);
+    }
+
+    #[test]
+    fn removing_the_flow_releases_a_throttled_pump() {
+        let flows = FlowRegistry::default();
+        let flow = flows.register("s1");
+        fill_past_high_watermark(&flow);
+
+        let remover = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            flows.remove("s1");
+        });
+        assert_eq!(admit(&flow, 10).0, Delivery::Send);
+        remover.join().unwrap();
+        assert!(!flow.lock().stats.dropping);
+    }
+}
@@ -0,0 +1,152 @@
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    pty::{PtySize, SpawnOptions},
+    scrollback::ScrollbackConfig,
+};
+
+/// File name of the profile store inside the app config directory.
+pub const PROFILES_FILE: &str = "profiles.json";
+
+/// Serializes read-modify-write cycles on the profile file.
+static STORE_LOCK: Mutex<()> = Mutex::new(());
+
+/// A named, reusable terminal setup.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TerminalProfile {
+    pub name: String,
+    #[serde(default)]
+    pub shell: Option<String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub cwd: Option<String>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// Value exported as `TERM`; defaults to `xterm-256color`.
+    #[serde(default)]
+    pub term: Option<String>,
+    /// Lines typed into the shell right after it starts.
+    #[serde(default)]
+    pub startup_commands: Vec<String>,
+    /// Load the bundled OSC 133 / OSC 7 integration script into the shell.
+    #[serde(default)]
+    pub shell_integration: bool,
+    #[serde(default)]
+    pub scrollback: ScrollbackConfig,
+}
+
+impl TerminalProfile {
+    pub fn spawn_options(&self, size: PtySize) -> SpawnOptions {
+        SpawnOptions {
+            program: self.shell.clone(),
+            args: self.args.clone(),
+            cwd: self.cwd.as_deref().map(expand_home),
+            env: self.env.clone(),
+            term: self.term.clone(),
+            size,
+        }
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct ProfilesFile {
+    #[serde(default)]
+    profiles: Vec<TerminalProfile>,
+}
+
+/// JSON-backed collection of terminal profiles.
+pub struct ProfileStore {
+    path: PathBuf,
+}
+
+impl ProfileStore {
+    pub fn new(config_dir: &Path) -> Self {
+        Self {
+            path: config_dir.join(PROFILES_FILE),
+        }
+    }
+
+    pub fn list(&self) -> Result<Vec<TerminalProfile>> {
+        let _guard = STORE_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+        Ok(self.read()?.profiles)
+    }
+
+    pub fn get(&self, name: &str) -> Result<TerminalProfile> {
+        self.list()?
+            .into_iter()
+            .find(|profile| profile.name == name)
+            .with_context(|| format!("terminal profile {name} not found"))
+    }
+
+    /// Inserts the profile, replacing any existing profile with the same name.
+    pub fn save(&self, mut profile: TerminalProfile) -> Result<TerminalProfile> {
+        profile.name = profile.name.trim().to_string();
+        if profile.name.is_empty() {
+            bail!("terminal profile name cannot be empty");
+        }
+
+        let _guard = STORE_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+        let mut file = self.read()?;
+        match file.profiles.iter_mut().find(|p| p.name == profile.name) {
+            Some(existing) => *existing = profile.clone(),
+            None => file.profiles.push(profile.clone()),
+        }
+        file.profiles.sort_by(|a, b| a.name.cmp(&b.name));
+        self.write(&file)?;
+        Ok(profile)
+    }
+
+    /// Removes the named profile, returning whether it existed.
+    pub fn delete(&self, name: &str) -> Result<bool> {
+        let _guard = STORE_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+        let mut file = self.read()?;
+        let before = file.profiles.len();
+        file.profiles.retain(|profile| profile.name != name);
+        if file.profiles.len() == before {
+            return Ok(false);
+        }
+        self.write(&file)?;
+        Ok(true)
+    }
+
+    fn read(&self) -> Result<ProfilesFile> {
+        if !self.path.exists() {
+            return Ok(ProfilesFile::default());
+        }
+        let raw = fs::read_to_string(&self.path)
+            .with_context(|| format!("failed to read {}", self.path.display()))?;
+        serde_json::from_str(&raw)
+            .with_context(|| format!("failed to parse {}", self.path.display()))
+    }
+
+    fn write(&self, file: &ProfilesFile) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create {}", parent.display()))?;
+        }
+        let raw = serde_json::to_string_pretty(file)?;
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, raw).with_context(|| format!("failed to write {}", tmp.display()))?;
+        fs::rename(&tmp, &self.path)
+            .with_context(|| format!("failed to replace {}", self.path.display()))
+    }
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    let home = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE"));
+    match (path.strip_prefix('~'), home) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+            PathBuf::from(home).join(rest.trim_start_matches('/'))
+        }
+        _ => PathBuf::from(path),
+    }
+}
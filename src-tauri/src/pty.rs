@@ -1,51 +1,276 @@
 use std::{
     collections::HashMap,
     env,
-    io::{Read, Write},
-    sync::Mutex,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, RecvTimeoutError, SyncSender, TrySendError},
+        Arc, Mutex, MutexGuard,
+    },
+    thread,
+    time::{Duration, Instant},
 };
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use once_cell::sync::Lazy;
-use portable_pty::{native_pty_system, Child, CommandBuilder, MasterPty, PtySize as RawPtySize};
+use portable_pty::{
+    native_pty_system, Child, CommandBuilder, ExitStatus, MasterPty, PtySize as RawPtySize,
+};
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::unix_millis;
+
+pub const DEFAULT_TERM: &str = "xterm-256color";
+
+/// How often a terminating session polls its child before escalating.
+const EXIT_POLL_INTERVAL: Duration = Duration::from_millis(25);
+/// How long a write waits for the child to take its input before failing.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(2);
+/// Writes that may queue up behind one the child has not taken yet.
+const WRITE_QUEUE_LEN: usize = 32;
+
 /// Global registry that keeps track of PTY sessions spawned by the backend.
 pub static PTY_REGISTRY: Lazy<PtyRegistry> = Lazy::new(PtyRegistry::default);
 
+/// Each session sits behind its own lock, so a slow operation on one session
+/// never holds up the others. The map lock is only held to look sessions up.
 #[derive(Default)]
 pub struct PtyRegistry {
-    sessions: Mutex<HashMap<String, PtySession>>,
+    sessions: Mutex<HashMap<String, Arc<Mutex<PtySession>>>>,
+    focused: Mutex<Option<String>>,
+    /// Named sets of sessions whose input is mirrored to each other. A session
+    /// belongs to at most one group.
+    sync_groups: Mutex<HashMap<String, Vec<String>>>,
+    /// Sessions with no child process behind them, such as recording
+    /// replays. They are listed, focused and closed like PTY sessions but
+    /// take no input.
+    read_only: Mutex<HashMap<String, SessionInfo>>,
 }
 
 impl PtyRegistry {
-    /// Spawns a new PTY session and stores it in the registry.
-    pub fn create_session(&self, size: PtySize, shell: Option<&str>) -> Result<String> {
-        let session = PtySession::spawn(size, shell)?;
+    /// Spawns a new PTY session, stores it in the registry and focuses it.
+    pub fn create_session(&self, options: SpawnOptions) -> Result<String> {
+        let session = PtySession::spawn(options)?;
         let id = session.id.clone();
-        self.sessions
-            .lock()
-            .expect("registry mutex poisoned")
-            .insert(id.clone(), session);
+        self.sessions()
+            .insert(id.clone(), Arc::new(Mutex::new(session)));
+        *self.focused() = Some(id.clone());
         Ok(id)
     }
 
-    pub fn remove_session(&self, id: &str) {
-        self.sessions
-            .lock()
-            .expect("registry mutex poisoned")
-            .remove(id);
+    /// Stores a read-only session and focuses it.
+    pub fn add_read_only(&self, mut info: SessionInfo) {
+        info.read_only = true;
+        let id = info.id.clone();
+        self.read_only().insert(id.clone(), info);
+        *self.focused() = Some(id);
+    }
+
+    /// Records a new size for a read-only session, as reported by its source.
+    pub fn resize_read_only(&self, id: &str, cols: u16, rows: u16) {
+        if let Some(info) = self.read_only().get_mut(id) {
+            info.cols = cols;
+            info.rows = rows;
+        }
     }
 
+    /// Removes a read-only session, returning whether it existed.
+    pub fn remove_read_only(&self, id: &str) -> bool {
+        if self.read_only().remove(id).is_none() {
+            return false;
+        }
+        let mut focused = self.focused();
+        if focused.as_deref() == Some(id) {
+            *focused = None;
+        }
+        true
+    }
+
+    /// Removes a session and terminates its child, escalating to SIGKILL if
+    /// it is still running after `grace`.
+    pub fn terminate_session(
+        &self,
+        id: &str,
+        signal: PtySignal,
+        grace: Duration,
+    ) -> Result<PtyExitStatus> {
+        let session = self
+            .sessions()
+            .remove(id)
+            .with_context(|| format!("PTY session {id} not found"))?;
+        {
+            let mut focused = self.focused();
+            if focused.as_deref() == Some(id) {
+                *focused = None;
+            }
+        }
+        self.leave_sync_groups(&[id.to_string()]);
+        let mut session = lock_session(&session);
+        session.terminate(signal, grace)
+    }
+
+    /// Lists every session, oldest first.
+    pub fn list_sessions(&self) -> Vec<SessionInfo> {
+        let sessions: Vec<_> = self.sessions().values().cloned().collect();
+        let mut infos: Vec<SessionInfo> = sessions
+            .iter()
+            .map(|session| {
+                let mut session = lock_session(session);
+                // A failed poll just leaves the session reported as alive.
+                let _ = session.try_reap();
+                session.info()
+            })
+            .collect();
+        infos.extend(self.read_only().values().cloned());
+        infos.sort_by_key(|info| info.created_at);
+        infos
+    }
+
+    pub fn focus_session(&self, id: &str) -> Result<()> {
+        if !self.sessions().contains_key(id) && !self.read_only().contains_key(id) {
+            bail!("PTY session {id} not found");
+        }
+        *self.focused() = Some(id.to_string());
+        Ok(())
+    }
+
+    pub fn focused_session(&self) -> Option<String> {
+        self.focused().clone()
+    }
+
+    /// Runs `f` with the session locked. Only callers using the same session
+    /// wait on it.
     pub fn with_session<F, R>(&self, id: &str, f: F) -> Result<R>
     where
         F: FnOnce(&mut PtySession) -> Result<R>,
     {
-        let mut sessions = self.sessions.lock().expect("registry mutex poisoned");
-        let session = sessions
-            .get_mut(id)
-            .with_context(|| format!("PTY session {id} not found"))?;
-        f(session)
+        let Some(session) = self.sessions().get(id).cloned() else {
+            if self.read_only().contains_key(id) {
+                bail!("session {id} is read-only");
+            }
+            bail!("PTY session {id} not found");
+        };
+        let mut session = lock_session(&session);
+        f(&mut session)
+    }
+
+    /// Waits for a session's child to exit once its output has closed and marks
+    /// the session dead. A child that lingers past `grace` is terminated.
+    pub fn reap_session(&self, id: &str, grace: Duration) -> Result<PtyExitStatus> {
+        let deadline = Instant::now() + grace;
+        while Instant::now() < deadline {
+            if let Some(status) = self.with_session(id, PtySession::try_reap)? {
+                return Ok(status);
+            }
+            thread::sleep(EXIT_POLL_INTERVAL);
+        }
+        self.with_session(id, |session| session.terminate(PtySignal::Sighup, grace))
+    }
+
+    /// Writes `bytes` to every session in `ids` concurrently, so one child that
+    /// is not reading input does not hold up the rest.
+    pub fn broadcast(&self, ids: &[String], bytes: &[u8]) -> Vec<(String, Result<()>)> {
+        let mut targets: Vec<&String> = Vec::with_capacity(ids.len());
+        for id in ids {
+            if !targets.contains(&id) {
+                targets.push(id);
+            }
+        }
+        if let [id] = targets[..] {
+            return vec![(
+                id.clone(),
+                self.with_session(id, |session| session.write(bytes)),
+            )];
+        }
+        thread::scope(|scope| {
+            let writes: Vec<_> = targets
+                .into_iter()
+                .map(|id| {
+                    let write =
+                        scope.spawn(move || self.with_session(id, |session| session.write(bytes)));
+                    (id, write)
+                })
+                .collect();
+            writes
+                .into_iter()
+                .map(|(id, write)| {
+                    let result = write
+                        .join()
+                        .unwrap_or_else(|_| Err(anyhow::anyhow!("PTY write panicked")));
+                    (id.clone(), result)
+                })
+                .collect()
+        })
+    }
+
+    /// Makes `ids` the members of sync group `name`, taking them out of any
+    /// other group. An empty list removes the group.
+    pub fn set_sync_group(&self, name: &str, ids: Vec<String>) -> Result<()> {
+        let name = name.trim();
+        if name.is_empty() {
+            bail!("sync group name cannot be empty");
+        }
+        {
+            let sessions = self.sessions();
+            if let Some(missing) = ids.iter().find(|id| !sessions.contains_key(*id)) {
+                bail!("PTY session {missing} not found");
+            }
+        }
+
+        let mut members: Vec<String> = Vec::with_capacity(ids.len());
+        for id in ids {
+            if !members.contains(&id) {
+                members.push(id);
+            }
+        }
+        if members.len() == 1 {
+            bail!("a sync group needs at least two sessions");
+        }
+        self.leave_sync_groups(&members);
+        let mut groups = self.sync_groups();
+        if members.is_empty() {
+            groups.remove(name);
+        } else {
+            groups.insert(name.to_string(), members);
+        }
+        Ok(())
+    }
+
+    pub fn list_sync_groups(&self) -> Vec<SyncGroup> {
+        let mut groups: Vec<SyncGroup> = self
+            .sync_groups()
+            .iter()
+            .map(|(name, session_ids)| SyncGroup {
+                name: name.clone(),
+                session_ids: session_ids.clone(),
+            })
+            .collect();
+        groups.sort_by(|a, b| a.name.cmp(&b.name));
+        groups
+    }
+
+    /// Sessions that input typed into `id` should reach: its sync group, with
+    /// `id` first, or just `id` when it is not in one.
+    pub fn sync_targets(&self, id: &str) -> Vec<String> {
+        let mut targets = vec![id.to_string()];
+        if let Some(members) = self
+            .sync_groups()
+            .values()
+            .find(|members| members.iter().any(|member| member == id))
+        {
+            targets.extend(members.iter().filter(|member| *member != id).cloned());
+        }
+        targets
+    }
+
+    fn leave_sync_groups(&self, ids: &[String]) {
+        let mut groups = self.sync_groups();
+        for members in groups.values_mut() {
+            members.retain(|member| !ids.contains(member));
+        }
+        // A group of one has nothing left to mirror to.
+        groups.retain(|_, members| members.len() > 1);
     }
 
     pub fn take_reader(&self, id: &str) -> Result<Box<dyn Read + Send>> {
@@ -55,31 +280,87 @@ impl PtyRegistry {
                 .with_context(|| format!("PTY reader for session {id} already taken"))
         })
     }
+
+    // A panic while a lock was held leaves the data consistent enough to keep
+    // going, so poisoning is ignored rather than taking every session down.
+    fn sessions(&self) -> MutexGuard<'_, HashMap<String, Arc<Mutex<PtySession>>>> {
+        self.sessions.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn read_only(&self) -> MutexGuard<'_, HashMap<String, SessionInfo>> {
+        self.read_only.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn focused(&self) -> MutexGuard<'_, Option<String>> {
+        self.focused.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn sync_groups(&self) -> MutexGuard<'_, HashMap<String, Vec<String>>> {
+        self.sync_groups
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+fn lock_session(session: &Mutex<PtySession>) -> MutexGuard<'_, PtySession> {
+    session.lock().unwrap_or_else(|err| err.into_inner())
 }
 
 pub struct PtySession {
     pub id: String,
+    title: String,
+    cwd: Option<PathBuf>,
+    created_at: u64,
+    size: PtySize,
+    term: String,
     master: Box<dyn MasterPty + Send>,
-    #[allow(dead_code)] // Kept alive to maintain the child process
     child: Box<dyn Child + Send>,
-    writer: Box<dyn Write + Send>,
+    writer: PtyWriter,
     reader: Option<Box<dyn Read + Send>>,
+    exit_status: Option<PtyExitStatus>,
 }
 
 impl PtySession {
-    fn spawn(size: PtySize, shell: Option<&str>) -> Result<Self> {
-        let shell_cmd = shell
-            .map(String::from)
-            .or_else(|| env::var("SHELL").ok())
-            .unwrap_or_else(|| "/bin/bash".to_string());
+    fn spawn(options: SpawnOptions) -> Result<Self> {
+        let SpawnOptions {
+            program,
+            args,
+            cwd,
+            env: extra_env,
+            term,
+            size,
+        } = options;
+
+        let shell_cmd = program.unwrap_or_else(default_shell);
 
         let system = native_pty_system();
         let pair = system
             .openpty(size.into())
             .context("failed to open PTY pair")?;
 
+        let title = Path::new(&shell_cmd)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| shell_cmd.clone());
+
         let mut cmd = CommandBuilder::new(shell_cmd);
-        cmd.env("TERM", "xterm-256color");
+        cmd.args(args);
+        if let Some(cwd) = &cwd {
+            if !cwd.is_dir() {
+                bail!("working directory {} does not exist", cwd.display());
+            }
+            cmd.cwd(cwd);
+        }
+        // An explicit TERM in the extra env still wins, as it is applied last.
+        let term = extra_env
+            .get("TERM")
+            .cloned()
+            .or(term)
+            .unwrap_or_else(|| DEFAULT_TERM.to_string());
+        cmd.env("TERM", &term);
+        for (key, value) in extra_env {
+            cmd.env(key, value);
+        }
 
         let child = pair
             .slave
@@ -91,36 +372,306 @@ impl PtySession {
             .try_clone_reader()
             .context("failed to clone PTY reader")?;
 
+        let id = Uuid::new_v4().to_string();
         let writer = pair
             .master
             .take_writer()
             .context("failed to take PTY writer")?;
+        let writer = PtyWriter::spawn(&id, writer)?;
 
         Ok(Self {
-            id: Uuid::new_v4().to_string(),
+            id,
+            title,
+            cwd,
+            created_at: unix_millis(),
+            size,
+            term,
             master: pair.master,
             child,
             writer,
             reader: Some(reader),
+            exit_status: None,
         })
     }
 
+    pub fn is_alive(&self) -> bool {
+        self.exit_status.is_none()
+    }
+
+    pub fn term(&self) -> &str {
+        &self.term
+    }
+
+    pub fn set_title(&mut self, title: &str) -> Result<()> {
+        let title = title.trim();
+        if title.is_empty() {
+            bail!("session title cannot be empty");
+        }
+        self.title = title.to_string();
+        Ok(())
+    }
+
+    pub fn info(&self) -> SessionInfo {
+        SessionInfo {
+            id: self.id.clone(),
+            title: self.title.clone(),
+            pid: self.child.process_id(),
+            cwd: self
+                .live_cwd()
+                .map(|path| path.to_string_lossy().into_owned()),
+            foreground: self.foreground_process(),
+            created_at: self.created_at,
+            alive: self.is_alive(),
+            cols: self.size.cols,
+            rows: self.size.rows,
+            read_only: false,
+        }
+    }
+
+    /// The shell's current directory, falling back to the directory it was
+    /// spawned in where the platform does not expose it.
+    pub fn live_cwd(&self) -> Option<PathBuf> {
+        if self.is_alive() {
+            if let Some(cwd) = self.child.process_id().and_then(proc_cwd) {
+                return Some(cwd);
+            }
+        }
+        self.cwd.clone()
+    }
+
+    /// The process group currently owning the terminal (vim, ssh, cargo, or
+    /// the shell itself when it is sitting at a prompt).
+    pub fn foreground_process(&self) -> Option<ForegroundProcess> {
+        if !self.is_alive() {
+            return None;
+        }
+        #[cfg(unix)]
+        {
+            let pid = u32::try_from(self.master.process_group_leader()?).ok()?;
+            Some(ForegroundProcess {
+                pid,
+                name: proc_name(pid),
+                is_shell: self.child.process_id() == Some(pid),
+            })
+        }
+        #[cfg(not(unix))]
+        {
+            None
+        }
+    }
+
     pub fn write(&mut self, bytes: &[u8]) -> Result<()> {
-        self.writer
-            .write_all(bytes)
-            .context("failed to write to PTY")?;
-        self.writer.flush().context("failed to flush PTY writer")
+        if !self.is_alive() {
+            bail!("PTY session {} has exited", self.id);
+        }
+        self.writer.write(bytes, WRITE_TIMEOUT)
     }
 
     pub fn resize(&mut self, size: PtySize) -> Result<()> {
         self.master
             .resize(size.into())
-            .context("failed to resize PTY")
+            .context("failed to resize PTY")?;
+        self.size = size;
+        Ok(())
     }
 
     pub fn take_reader(&mut self) -> Option<Box<dyn Read + Send>> {
         self.reader.take()
     }
+
+    /// Polls the child without blocking, recording its exit status once it has
+    /// been reaped.
+    pub fn try_reap(&mut self) -> Result<Option<PtyExitStatus>> {
+        if self.exit_status.is_none() {
+            self.exit_status = self
+                .child
+                .try_wait()
+                .context("failed to poll child process")?
+                .map(Into::into);
+        }
+        Ok(self.exit_status.clone())
+    }
+
+    /// Signals the child and waits up to `grace` for it to exit, escalating to
+    /// SIGKILL if it is still running afterwards.
+    pub fn terminate(&mut self, signal: PtySignal, grace: Duration) -> Result<PtyExitStatus> {
+        if let Some(status) = self.try_reap()? {
+            return Ok(status);
+        }
+
+        self.signal(signal)?;
+        let deadline = Instant::now() + grace;
+        while Instant::now() < deadline {
+            if let Some(status) = self.try_reap()? {
+                return Ok(status);
+            }
+            thread::sleep(EXIT_POLL_INTERVAL);
+        }
+
+        self.signal(PtySignal::Sigkill)?;
+        let status: PtyExitStatus = self
+            .child
+            .wait()
+            .context("failed to reap child process")?
+            .into();
+        self.exit_status = Some(status.clone());
+        Ok(status)
+    }
+
+    #[cfg(unix)]
+    fn signal(&mut self, signal: PtySignal) -> Result<()> {
+        let Some(pid) = self.child.process_id() else {
+            return Ok(());
+        };
+        let raw = match signal {
+            PtySignal::Sighup => libc::SIGHUP,
+            PtySignal::Sigterm => libc::SIGTERM,
+            PtySignal::Sigkill => libc::SIGKILL,
+        };
+        if unsafe { libc::kill(pid as libc::pid_t, raw) } != 0 {
+            let err = std::io::Error::last_os_error();
+            // The child may already be gone; the following wait will reap it.
+            if err.raw_os_error() != Some(libc::ESRCH) {
+                return Err(err).context("failed to signal child process");
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn signal(&mut self, _signal: PtySignal) -> Result<()> {
+        self.child.kill().context("failed to kill child process")
+    }
+}
+
+/// Feeds a session's input from a dedicated thread. A child that stops
+/// reading stdin only stalls that thread; callers get an error after a
+/// timeout instead of hanging.
+struct PtyWriter {
+    jobs: SyncSender<WriteJob>,
+}
+
+struct WriteJob {
+    bytes: Vec<u8>,
+    done: mpsc::Sender<io::Result<()>>,
+}
+
+impl PtyWriter {
+    fn spawn(session_id: &str, mut writer: Box<dyn Write + Send>) -> Result<Self> {
+        let (jobs, queue) = mpsc::sync_channel::<WriteJob>(WRITE_QUEUE_LEN);
+        thread::Builder::new()
+            .name(format!("pty-writer-{session_id}"))
+            .spawn(move || {
+                for job in queue {
+                    let result = writer.write_all(&job.bytes).and_then(|()| writer.flush());
+                    let _ = job.done.send(result);
+                }
+            })
+            .context("failed to start PTY writer thread")?;
+        Ok(Self { jobs })
+    }
+
+    /// Queues `bytes` and waits up to `timeout` for them to be written. Input
+    /// that times out stays queued and reaches the child if it resumes
+    /// reading.
+    fn write(&self, bytes: &[u8], timeout: Duration) -> Result<()> {
+        let (done, written) = mpsc::channel();
+        let job = WriteJob {
+            bytes: bytes.to_vec(),
+            done,
+        };
+        match self.jobs.try_send(job) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                bail!("PTY input is backed up; the program is not reading it")
+            }
+            Err(TrySendError::Disconnected(_)) => bail!("PTY writer has stopped"),
+        }
+        match written.recv_timeout(timeout) {
+            Ok(result) => result.context("failed to write to PTY"),
+            Err(RecvTimeoutError::Timeout) => {
+                bail!("timed out writing to PTY; the program is not reading input")
+            }
+            Err(RecvTimeoutError::Disconnected) => bail!("PTY writer has stopped"),
+        }
+    }
+}
+
+/// Snapshot of a session's metadata for the frontend tab bar.
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionInfo {
+    pub id: String,
+    pub title: String,
+    pub pid: Option<u32>,
+    pub cwd: Option<String>,
+    pub foreground: Option<ForegroundProcess>,
+    /// Milliseconds since the Unix epoch.
+    pub created_at: u64,
+    pub alive: bool,
+    pub cols: u16,
+    pub rows: u16,
+    /// Output only, such as a replayed recording; input is rejected.
+    pub read_only: bool,
+}
+
+/// Sessions whose input is mirrored to each other, like tmux's
+/// `synchronize-panes`.
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncGroup {
+    pub name: String,
+    pub session_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ForegroundProcess {
+    pub pid: u32,
+    pub name: Option<String>,
+    pub is_shell: bool,
+}
+
+/// Signals that can be delivered to a PTY child when closing a session.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum PtySignal {
+    #[default]
+    Sighup,
+    Sigterm,
+    Sigkill,
+}
+
+/// Exit status of a PTY child, in a shape the frontend can consume.
+#[derive(Debug, Clone, Serialize)]
+pub struct PtyExitStatus {
+    pub exit_code: u32,
+    pub signal: Option<String>,
+}
+
+impl From<ExitStatus> for PtyExitStatus {
+    fn from(status: ExitStatus) -> Self {
+        // portable-pty only exposes the signal name through `Display`.
+        let signal = status
+            .to_string()
+            .strip_prefix("Terminated by ")
+            .map(String::from);
+        Self {
+            exit_code: status.exit_code(),
+            signal,
+        }
+    }
+}
+
+/// Describes the program a new PTY session runs and the terminal it starts with.
+#[derive(Debug, Clone, Default)]
+pub struct SpawnOptions {
+    /// Program to execute; falls back to `$SHELL`, then `/bin/bash`.
+    pub program: Option<String>,
+    pub args: Vec<String>,
+    pub cwd: Option<PathBuf>,
+    /// Extra environment variables, applied after the defaults so they can override `TERM`.
+    pub env: HashMap<String, String>,
+    /// Value exported as `TERM`; defaults to [`DEFAULT_TERM`].
+    pub term: Option<String>,
+    pub size: PtySize,
 }
 
 /// High-level PTY size abstraction used by the frontend/backed bridge.
@@ -143,6 +694,34 @@ impl Default for PtySize {
     }
 }
 
+#[cfg(target_os = "linux")]
+fn proc_cwd(pid: u32) -> Option<PathBuf> {
+    std::fs::read_link(format!("/proc/{pid}/cwd")).ok()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn proc_cwd(_pid: u32) -> Option<PathBuf> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn proc_name(pid: u32) -> Option<String> {
+    std::fs::read_to_string(format!("/proc/{pid}/comm"))
+        .ok()
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn proc_name(_pid: u32) -> Option<String> {
+    None
+}
+
+/// The user's login shell, falling back to bash.
+pub fn default_shell() -> String {
+    env::var("SHELL").unwrap_or_else(|_| "/bin/bash".to_string())
+}
+
 impl From<PtySize> for RawPtySize {
     fn from(value: PtySize) -> Self {
         RawPtySize {
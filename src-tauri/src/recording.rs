@@ -0,0 +1,272 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard, RwLock},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use serde_json::json;
+
+use crate::{unix_millis, utf8::Utf8Decoder};
+
+/// Recorder slots keyed by PTY session id.
+pub static RECORDINGS: Lazy<RecordingRegistry> = Lazy::new(RecordingRegistry::default);
+
+/// Buffered events are written out when an event arrives this long after the
+/// last flush, and when the recording finishes.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// One session's recorder slot behind its own lock, empty while the session
+/// is not being recorded. The session's reader keeps a handle, so recording
+/// output never touches the registry.
+#[derive(Clone)]
+pub struct RecorderHandle(Arc<RecorderSlot>);
+
+struct RecorderSlot {
+    session_id: String,
+    recorder: Mutex<Option<CastRecorder>>,
+}
+
+impl RecorderHandle {
+    pub fn output(&self, bytes: &[u8]) {
+        self.record(|recorder| recorder.output(bytes));
+    }
+
+    pub fn input(&self, bytes: &[u8]) {
+        self.record(|recorder| recorder.input(bytes));
+    }
+
+    pub fn resize(&self, cols: u16, rows: u16) {
+        self.record(|recorder| recorder.resize(cols, rows));
+    }
+
+    fn start(&self, recorder: CastRecorder) -> Result<()> {
+        let mut slot = self.lock();
+        if slot.is_some() {
+            bail!("session {} is already being recorded", self.0.session_id);
+        }
+        *slot = Some(recorder);
+        Ok(())
+    }
+
+    fn stop(&self) -> Option<Result<RecordingSummary>> {
+        let recorder = self.lock().take()?;
+        Some(recorder.finish())
+    }
+
+    /// A failed write ends the recording rather than interrupting the session.
+    fn record<F>(&self, write: F)
+    where
+        F: FnOnce(&mut CastRecorder) -> Result<()>,
+    {
+        let mut slot = self.lock();
+        let Some(recorder) = slot.as_mut() else {
+            return;
+        };
+        if let Err(err) = write(recorder) {
+            eprintln!(
+                "stopping recording of session {}: {err:#}",
+                self.0.session_id
+            );
+            *slot = None;
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Option<CastRecorder>> {
+        self.0
+            .recorder
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// Recorder handles keyed by session id. The map lock is only taken to look
+/// a session up, never while an event is written.
+#[derive(Default)]
+pub struct RecordingRegistry {
+    sessions: RwLock<HashMap<String, RecorderHandle>>,
+}
+
+impl RecordingRegistry {
+    /// Creates an empty slot for `session_id`, replacing any previous one,
+    /// and returns a handle the session's reader can keep.
+    pub fn register(&self, session_id: &str) -> RecorderHandle {
+        let handle = RecorderHandle(Arc::new(RecorderSlot {
+            session_id: session_id.to_string(),
+            recorder: Mutex::new(None),
+        }));
+        self.sessions
+            .write()
+            .unwrap_or_else(|err| err.into_inner())
+            .insert(session_id.to_string(), handle.clone());
+        handle
+    }
+
+    pub fn start(&self, session_id: &str, recorder: CastRecorder) -> Result<()> {
+        match self.get(session_id) {
+            Some(handle) => handle.start(recorder),
+            None => bail!("session {session_id} not found"),
+        }
+    }
+
+    /// Finishes the session's recording, if one is running.
+    pub fn stop(&self, session_id: &str) -> Option<Result<RecordingSummary>> {
+        self.get(session_id)?.stop()
+    }
+
+    /// Drops the session's slot, finishing its recording if one is running.
+    pub fn remove(&self, session_id: &str) -> Option<Result<RecordingSummary>> {
+        self.sessions
+            .write()
+            .unwrap_or_else(|err| err.into_inner())
+            .remove(session_id)?
+            .stop()
+    }
+
+    pub fn input(&self, session_id: &str, bytes: &[u8]) {
+        if let Some(handle) = self.get(session_id) {
+            handle.input(bytes);
+        }
+    }
+
+    pub fn resize(&self, session_id: &str, cols: u16, rows: u16) {
+        if let Some(handle) = self.get(session_id) {
+            handle.resize(cols, rows);
+        }
+    }
+
+    fn get(&self, session_id: &str) -> Option<RecorderHandle> {
+        self.sessions
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .get(session_id)
+            .cloned()
+    }
+}
+
+/// Fields written to the asciicast header.
+#[derive(Debug, Clone, Default)]
+pub struct CastHeader {
+    pub cols: u16,
+    pub rows: u16,
+    pub title: Option<String>,
+    pub term: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordingSummary {
+    pub path: String,
+    pub duration_ms: u64,
+    pub events: u64,
+}
+
+/// Writes an asciicast v2 file: a JSON header line followed by one
+/// `[seconds, code, data]` event per line.
+pub struct CastRecorder {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    started: Instant,
+    flushed: Instant,
+    record_input: bool,
+    events: u64,
+    output: Utf8Decoder,
+    input: Utf8Decoder,
+}
+
+impl CastRecorder {
+    pub fn create(path: &Path, header: &CastHeader, record_input: bool) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create {}", parent.display()))?;
+        }
+        let file =
+            File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+
+        let timestamp = unix_millis() / 1000;
+        let mut header_json = json!({
+            "version": 2,
+            "width": header.cols,
+            "height": header.rows,
+            "timestamp": timestamp,
+        });
+        if let Some(term) = &header.term {
+            header_json["env"] = json!({ "TERM": term });
+        }
+        if let Some(title) = &header.title {
+            header_json["title"] = json!(title);
+        }
+
+        let mut recorder = Self {
+            path: path.to_path_buf(),
+            writer: BufWriter::new(file),
+            started: Instant::now(),
+            flushed: Instant::now(),
+            record_input,
+            events: 0,
+            output: Utf8Decoder::default(),
+            input: Utf8Decoder::default(),
+        };
+        recorder.write_line(&header_json)?;
+        Ok(recorder)
+    }
+
+    pub fn output(&mut self, bytes: &[u8]) -> Result<()> {
+        let text = self.output.decode(bytes);
+        self.event("o", &text)
+    }
+
+    pub fn input(&mut self, bytes: &[u8]) -> Result<()> {
+        if !self.record_input {
+            return Ok(());
+        }
+        let text = self.input.decode(bytes);
+        self.event("i", &text)
+    }
+
+    pub fn resize(&mut self, cols: u16, rows: u16) -> Result<()> {
+        self.event("r", &format!("{cols}x{rows}"))
+    }
+
+    pub fn finish(mut self) -> Result<RecordingSummary> {
+        self.writer
+            .flush()
+            .with_context(|| format!("failed to write {}", self.path.display()))?;
+        Ok(RecordingSummary {
+            path: self.path.display().to_string(),
+            duration_ms: self.started.elapsed().as_millis() as u64,
+            events: self.events,
+        })
+    }
+
+    fn event(&mut self, code: &str, data: &str) -> Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        let elapsed = self.started.elapsed().as_secs_f64();
+        // Six decimals matches what asciinema itself writes.
+        let time = (elapsed * 1_000_000.0).round() / 1_000_000.0;
+        self.write_line(&json!([time, code, data]))?;
+        self.events += 1;
+        // Lines are complete when flushed, so a crash still leaves a
+        // playable file that is missing at most the last interval.
+        if self.flushed.elapsed() >= FLUSH_INTERVAL {
+            self.writer
+                .flush()
+                .with_context(|| format!("failed to write {}", self.path.display()))?;
+            self.flushed = Instant::now();
+        }
+        Ok(())
+    }
+
+    fn write_line(&mut self, value: &serde_json::Value) -> Result<()> {
+        serde_json::to_writer(&mut self.writer, value)?;
+        self.writer
+            .write_all(b"\n")
+            .with_context(|| format!("failed to write {}", self.path.display()))
+    }
+}
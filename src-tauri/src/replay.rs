@@ -0,0 +1,566 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader},
+    mem,
+    path::Path,
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+pub const REPLAY_SPEED_MIN: f64 = 0.1;
+pub const REPLAY_SPEED_MAX: f64 = 16.0;
+
+/// Replays in progress, keyed by their pseudo-session id.
+pub static REPLAYS: Lazy<ReplayRegistry> = Lazy::new(ReplayRegistry::default);
+
+/// A parsed asciicast v2 recording. Input events are dropped; only output and
+/// resize events affect what a viewer sees.
+pub struct CastFile {
+    pub cols: u16,
+    pub rows: u16,
+    pub title: Option<String>,
+    events: Vec<CastEvent>,
+}
+
+struct CastEvent {
+    at_ms: u64,
+    frame: CastFrame,
+}
+
+enum CastFrame {
+    Output(String),
+    Resize { cols: u16, rows: u16 },
+}
+
+impl CastFile {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file =
+            File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+        Self::parse(BufReader::new(file), &path.display().to_string())
+    }
+
+    /// Parses a recording from `reader`. `name` identifies it in errors.
+    fn parse(reader: impl BufRead, name: &str) -> Result<Self> {
+        let mut lines = reader.lines();
+
+        let header: Value = match lines.next() {
+            Some(line) => serde_json::from_str(&line?)
+                .with_context(|| format!("{name} has an invalid header"))?,
+            None => bail!("{name} is empty"),
+        };
+        if header["version"].as_u64() != Some(2) {
+            bail!("{name} is not an asciicast v2 file");
+        }
+        let dimension = |key: &str| {
+            header[key]
+                .as_u64()
+                .map(|value| value.clamp(1, u16::MAX as u64) as u16)
+                .with_context(|| format!("{name} header is missing {key}"))
+        };
+        let cols = dimension("width")?;
+        let rows = dimension("height")?;
+        let title = header["title"].as_str().map(str::to_string);
+        // Recordings may cap idle gaps; honour it the way `asciinema play` does.
+        let idle_limit = header["idle_time_limit"]
+            .as_f64()
+            .filter(|limit| *limit > 0.0);
+
+        let mut events = Vec::new();
+        let mut last_time = 0.0;
+        let mut at = 0.0;
+        for (number, line) in lines.enumerate() {
+            let line = line.with_context(|| format!("failed to read {name}"))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (time, code, data): (f64, String, String) = serde_json::from_str(&line)
+                .with_context(|| format!("{name} line {} is not a valid event", number + 2))?;
+
+            let gap = (time - last_time).max(0.0);
+            at += idle_limit.map_or(gap, |limit| gap.min(limit));
+            last_time = time;
+
+            let frame = match code.as_str() {
+                "o" => CastFrame::Output(data),
+                "r" => match data.split_once('x') {
+                    Some((cols, rows)) => match (cols.parse(), rows.parse()) {
+                        (Ok(cols), Ok(rows)) => CastFrame::Resize { cols, rows },
+                        _ => continue,
+                    },
+                    None => continue,
+                },
+                _ => continue,
+            };
+            events.push(CastEvent {
+                at_ms: (at * 1000.0).round() as u64,
+                frame,
+            });
+        }
+
+        Ok(Self {
+            cols,
+            rows,
+            title,
+            events,
+        })
+    }
+
+    pub fn duration_ms(&self) -> u64 {
+        self.events.last().map_or(0, |event| event.at_ms)
+    }
+}
+
+/// Something the replay loop wants shown to the viewer.
+pub enum ReplayEvent<'a> {
+    Output(&'a str),
+    Resize {
+        cols: u16,
+        rows: u16,
+    },
+    /// The screen must be cleared because playback jumped backwards.
+    Reset,
+    Status(ReplayStatus),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum ReplayAction {
+    Pause,
+    /// Continues playback; at the end of the recording it starts over.
+    Resume,
+    Seek {
+        position_ms: u64,
+    },
+    SetSpeed {
+        speed: f64,
+    },
+    Stop,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplayStatus {
+    pub position_ms: u64,
+    pub duration_ms: u64,
+    pub speed: f64,
+    pub paused: bool,
+    pub finished: bool,
+}
+
+struct PlaybackState {
+    position_ms: u64,
+    duration_ms: u64,
+    speed: f64,
+    paused: bool,
+    finished: bool,
+    stopped: bool,
+    seek: Option<u64>,
+}
+
+impl PlaybackState {
+    fn status(&self) -> ReplayStatus {
+        ReplayStatus {
+            position_ms: self.position_ms,
+            duration_ms: self.duration_ms,
+            speed: self.speed,
+            paused: self.paused,
+            finished: self.finished,
+        }
+    }
+}
+
+/// Shared between the playback loop and the commands that steer it.
+pub struct ReplayControl {
+    state: Mutex<PlaybackState>,
+    wake: Condvar,
+}
+
+impl ReplayControl {
+    fn lock(&self) -> MutexGuard<'_, PlaybackState> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+#[derive(Default)]
+pub struct ReplayRegistry {
+    replays: Mutex<HashMap<String, Arc<ReplayControl>>>,
+}
+
+impl ReplayRegistry {
+    /// Registers a new replay and returns its pseudo-session id.
+    pub fn register(&self, duration_ms: u64, speed: f64) -> (String, Arc<ReplayControl>) {
+        let id = format!("replay-{}", Uuid::new_v4());
+        let control = Arc::new(ReplayControl {
+            state: Mutex::new(PlaybackState {
+                position_ms: 0,
+                duration_ms,
+                speed: clamp_speed(speed),
+                paused: false,
+                finished: false,
+                stopped: false,
+                seek: None,
+            }),
+            wake: Condvar::new(),
+        });
+        self.lock().insert(id.clone(), control.clone());
+        (id, control)
+    }
+
+    pub fn control(&self, id: &str, action: ReplayAction) -> Result<ReplayStatus> {
+        let control = self
+            .lock()
+            .get(id)
+            .cloned()
+            .with_context(|| format!("replay {id} not found"))?;
+
+        let mut state = control.lock();
+        match action {
+            ReplayAction::Pause => state.paused = true,
+            ReplayAction::Resume => {
+                state.paused = false;
+                if state.finished {
+                    state.seek = Some(0);
+                }
+            }
+            ReplayAction::Seek { position_ms } => state.seek = Some(position_ms),
+            ReplayAction::SetSpeed { speed } => state.speed = clamp_speed(speed),
+            ReplayAction::Stop => state.stopped = true,
+        }
+        let mut status = state.status();
+        if let Some(target) = state.seek {
+            status.position_ms = target.min(state.duration_ms);
+            status.finished = false;
+        }
+        control.wake.notify_all();
+        Ok(status)
+    }
+
+    pub fn remove(&self, id: &str) {
+        self.lock().remove(id);
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, Arc<ReplayControl>>> {
+        self.replays.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+fn clamp_speed(speed: f64) -> f64 {
+    if speed.is_finite() {
+        speed.clamp(REPLAY_SPEED_MIN, REPLAY_SPEED_MAX)
+    } else {
+        1.0
+    }
+}
+
+/// Plays `cast` with its original timing until the replay is stopped. Stays
+/// alive at the end of the recording so the viewer can still seek.
+pub fn run<F>(control: &ReplayControl, cast: &CastFile, mut emit: F)
+where
+    F: FnMut(ReplayEvent<'_>),
+{
+    let events = &cast.events;
+    let mut index = 0;
+    let mut state = control.lock();
+
+    loop {
+        if state.stopped {
+            return;
+        }
+
+        if let Some(target) = state.seek.take() {
+            let target = target.min(state.duration_ms);
+            let rewind = target < state.position_ms || (target == 0 && index > 0);
+            state.position_ms = target;
+            drop(state);
+
+            if rewind {
+                emit(ReplayEvent::Reset);
+                index = 0;
+            }
+            // Jumping forward replays everything in between at once.
+            let mut output = String::new();
+            while index < events.len() && events[index].at_ms <= target {
+                match &events[index].frame {
+                    CastFrame::Output(text) => output.push_str(text),
+                    CastFrame::Resize { cols, rows } => {
+                        if !output.is_empty() {
+                            emit(ReplayEvent::Output(&mem::take(&mut output)));
+                        }
+                        emit(ReplayEvent::Resize {
+                            cols: *cols,
+                            rows: *rows,
+                        });
+                    }
+                }
+                index += 1;
+            }
+            if !output.is_empty() {
+                emit(ReplayEvent::Output(&output));
+            }
+
+            state = control.lock();
+            state.finished = index == events.len();
+            emit(ReplayEvent::Status(state.status()));
+            continue;
+        }
+
+        if index == events.len() && !state.finished {
+            state.finished = true;
+            state.position_ms = state.duration_ms;
+            emit(ReplayEvent::Status(state.status()));
+        }
+        if state.paused || state.finished {
+            state = control
+                .wake
+                .wait(state)
+                .unwrap_or_else(|err| err.into_inner());
+            continue;
+        }
+
+        let event = &events[index];
+        let speed = state.speed;
+        let gap_ms = event.at_ms.saturating_sub(state.position_ms);
+        let wait = Duration::from_secs_f64(gap_ms as f64 / 1000.0 / speed);
+        let started = Instant::now();
+        let (guard, timeout) = control
+            .wake
+            .wait_timeout(state, wait)
+            .unwrap_or_else(|err| err.into_inner());
+        state = guard;
+
+        if !timeout.timed_out() {
+            // Woken by a control change: account for the time already played.
+            let played = (started.elapsed().as_secs_f64() * 1000.0 * speed) as u64;
+            state.position_ms = (state.position_ms + played).min(event.at_ms);
+            continue;
+        }
+
+        state.position_ms = event.at_ms;
+        index += 1;
+        drop(state);
+        match &event.frame {
+            CastFrame::Output(text) => emit(ReplayEvent::Output(text)),
+            CastFrame::Resize { cols, rows } => emit(ReplayEvent::Resize {
+                cols: *cols,
+                rows: *rows,
+            }),
+        }
+        state = control.lock();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::mpsc, thread};
+
+    use super::*;
+
+    fn parse(cast: &str) -> Result<CastFile> {
+        CastFile::parse(cast.as_bytes(), "test.cast")
+    }
+
+    fn frames(cast: &CastFile) -> Vec<(u64, String)> {
+        cast.events
+            .iter()
+            .map(|event| {
+                let frame = match &event.frame {
+                    CastFrame::Output(text) => text.clone(),
+                    CastFrame::Resize { cols, rows } => format!("resize {cols}x{rows}"),
+                };
+                (event.at_ms, frame)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn header_and_events_are_parsed() {
+        let cast = parse(concat!(
+            r#"{"version": 2, "width": 100, "height": 30, "title": "demo"}"#,
+            "\n",
+            r#"[0.5, "o", "hello "]"#,
+            "\n\n",
+            r#"[0.75, "i", "typed"]"#,
+            "\n",
+            r#"[1.25, "r", "120x40"]"#,
+            "\n",
+            r#"[2.0, "o", "world"]"#,
+            "\n",
+        ))
+        .unwrap();
+
+        assert_eq!((cast.cols, cast.rows), (100, 30));
+        assert_eq!(cast.title.as_deref(), Some("demo"));
+        assert_eq!(
+            frames(&cast),
+            [
+                (500, "hello ".to_string()),
+                (1250, "resize 120x40".to_string()),
+                (2000, "world".to_string()),
+            ]
+        );
+        assert_eq!(cast.duration_ms(), 2000);
+    }
+
+    #[test]
+    fn idle_time_limit_caps_every_gap() {
+        let cast = parse(concat!(
+            r#"{"version": 2, "width": 80, "height": 24, "idle_time_limit": 1.5}"#,
+            "\n",
+            r#"[10.0, "o", "a"]"#,
+            "\n",
+            r#"[10.5, "o", "b"]"#,
+            "\n",
+            r#"[40.0, "o", "c"]"#,
+            "\n",
+        ))
+        .unwrap();
+
+        let times: Vec<u64> = frames(&cast).iter().map(|(at, _)| *at).collect();
+        assert_eq!(times, [1500, 2000, 3500]);
+    }
+
+    #[test]
+    fn timestamps_going_backwards_do_not_rewind() {
+        let cast = parse(concat!(
+            r#"{"version": 2, "width": 80, "height": 24}"#,
+            "\n",
+            r#"[2.0, "o", "a"]"#,
+            "\n",
+            r#"[1.0, "o", "b"]"#,
+            "\n",
+        ))
+        .unwrap();
+
+        let times: Vec<u64> = frames(&cast).iter().map(|(at, _)| *at).collect();
+        assert_eq!(times, [2000, 2000]);
+    }
+
+    #[test]
+    fn unknown_codes_and_bad_resizes_are_skipped() {
+        let cast = parse(concat!(
+            r#"{"version": 2, "width": 0, "height": 24}"#,
+            "\n",
+            r#"[0.1, "m", "marker"]"#,
+            "\n",
+            r#"[0.2, "r", "wide"]"#,
+            "\n",
+            r#"[0.3, "r", "80xtall"]"#,
+            "\n",
+            r#"[0.4, "o", "kept"]"#,
+            "\n",
+        ))
+        .unwrap();
+
+        assert_eq!(cast.cols, 1);
+        assert_eq!(frames(&cast), [(400, "kept".to_string())]);
+    }
+
+    #[test]
+    fn malformed_files_are_rejected() {
+        let header = r#"{"version": 2, "width": 80, "height": 24}"#;
+        let cases = [
+            ("", "test.cast is empty"),
+            ("not json", "test.cast has an invalid header"),
+            (
+                r#"{"version": 1, "width": 80, "height": 24}"#,
+                "not an asciicast v2",
+            ),
+            (r#"{"version": 2, "width": 80}"#, "header is missing height"),
+            (
+                &format!("{header}\n[0.1, \"o\"]"),
+                "line 2 is not a valid event",
+            ),
+            (
+                &format!("{header}\n\n[\"x\", \"o\", \"a\"]"),
+                "line 3 is not a valid event",
+            ),
+        ];
+        for (cast, expected) in cases {
+            let err = parse(cast).err().expect(cast);
+            assert!(err.to_string().contains(expected), "{cast:?}: {err}");
+        }
+    }
+
+    #[test]
+    fn speed_is_clamped() {
+        assert_eq!(clamp_speed(0.0), REPLAY_SPEED_MIN);
+        assert_eq!(clamp_speed(-3.0), REPLAY_SPEED_MIN);
+        assert_eq!(clamp_speed(100.0), REPLAY_SPEED_MAX);
+        assert_eq!(clamp_speed(f64::NAN), 1.0);
+        assert_eq!(clamp_speed(f64::INFINITY), 1.0);
+        assert_eq!(clamp_speed(2.5), 2.5);
+
+        let replays = ReplayRegistry::default();
+        let (id, _) = replays.register(1000, -1.0);
+        let status = replays.control(&id, ReplayAction::Pause).unwrap();
+        assert_eq!(status.speed, REPLAY_SPEED_MIN);
+        let status = replays
+            .control(&id, ReplayAction::SetSpeed { speed: 0.0 })
+            .unwrap();
+        assert_eq!(status.speed, REPLAY_SPEED_MIN);
+    }
+
+    #[test]
+    fn seek_past_the_end_is_clamped_and_plays_everything() {
+        let cast = parse(concat!(
+            r#"{"version": 2, "width": 80, "height": 24}"#,
+            "\n",
+            r#"[1.0, "o", "a"]"#,
+            "\n",
+            r#"[2.0, "r", "90x30"]"#,
+            "\n",
+            r#"[3.0, "o", "b"]"#,
+            "\n",
+            r#"[4.0, "o", "c"]"#,
+            "\n",
+        ))
+        .unwrap();
+        let replays = ReplayRegistry::default();
+        let (id, control) = replays.register(cast.duration_ms(), 1.0);
+
+        let status = replays
+            .control(
+                &id,
+                ReplayAction::Seek {
+                    position_ms: 60_000,
+                },
+            )
+            .unwrap();
+        assert_eq!(status.position_ms, 4000);
+        assert!(!status.finished);
+
+        let (tx, rx) = mpsc::channel();
+        let player = thread::spawn(move || {
+            run(&control, &cast, |event| {
+                let seen = match event {
+                    ReplayEvent::Output(text) => text.to_string(),
+                    ReplayEvent::Resize { cols, rows } => format!("resize {cols}x{rows}"),
+                    ReplayEvent::Reset => "reset".to_string(),
+                    ReplayEvent::Status(status) => {
+                        format!("at {} finished {}", status.position_ms, status.finished)
+                    }
+                };
+                let _ = tx.send(seen);
+            })
+        });
+
+        let seen: Vec<String> = rx.iter().take(4).collect();
+        assert_eq!(seen, ["a", "resize 90x30", "bc", "at 4000 finished true"]);
+
+        // Seeking back to the start clears the screen first.
+        replays
+            .control(&id, ReplayAction::Seek { position_ms: 0 })
+            .unwrap();
+        let seen: Vec<String> = rx.iter().take(2).collect();
+        assert_eq!(seen, ["reset", "at 0 finished false"]);
+
+        replays.control(&id, ReplayAction::Stop).unwrap();
+        player.join().unwrap();
+    }
+}
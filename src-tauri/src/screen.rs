@@ -0,0 +1,932 @@
+use std::{collections::VecDeque, fmt::Write, mem, ops::Range};
+
+use unicode_width::UnicodeWidthChar;
+use vte::{Params, Parser, Perform};
+
+use crate::export;
+
+const TAB_WIDTH: usize = 8;
+
+/// Terminal colour as set through SGR sequences.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Color {
+    #[default]
+    Default,
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+/// Rendition attributes applied to a cell.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Style {
+    pub fg: Color,
+    pub bg: Color,
+    pub bold: bool,
+    pub dim: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub inverse: bool,
+    pub strikethrough: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cell {
+    /// `'\0'` marks the second column of a wide character.
+    pub ch: char,
+    pub style: Style,
+}
+
+impl Cell {
+    fn blank(style: Style) -> Self {
+        Self {
+            ch: ' ',
+            style: Style {
+                bg: style.bg,
+                ..Style::default()
+            },
+        }
+    }
+
+    pub fn is_wide_spacer(&self) -> bool {
+        self.ch == '\0'
+    }
+}
+
+/// One row of the grid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    pub cells: Vec<Cell>,
+    /// Set when the row was soft-wrapped into the following row.
+    pub wrapped: bool,
+}
+
+impl Line {
+    fn new(cols: usize, style: Style) -> Self {
+        Self {
+            cells: vec![Cell::blank(style); cols],
+            wrapped: false,
+        }
+    }
+
+    fn resize(&mut self, cols: usize) {
+        self.cells.resize(cols, Cell::blank(Style::default()));
+    }
+
+    /// Row text with trailing blanks removed.
+    pub fn text(&self) -> String {
+        self.raw_text().trim_end().to_string()
+    }
+
+    /// Row text including trailing blanks, as needed when joining wrapped rows.
+    pub fn raw_text(&self) -> String {
+        self.cells
+            .iter()
+            .filter(|cell| !cell.is_wide_spacer())
+            .map(|cell| cell.ch)
+            .collect()
+    }
+
+    fn is_blank(&self) -> bool {
+        self.cells.iter().all(|cell| cell.ch == ' ')
+    }
+
+    /// Cells up to the last one that shows anything, either a character or
+    /// a non-default pen such as a background colour or underline.
+    pub(crate) fn content_len(&self) -> usize {
+        self.cells
+            .iter()
+            .rposition(|cell| cell.ch != ' ' || cell.style != Style::default())
+            .map_or(0, |idx| idx + 1)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Cursor {
+    row: usize,
+    col: usize,
+    style: Style,
+    /// The last column was written and the next print wraps first.
+    wrap_pending: bool,
+}
+
+impl Cursor {
+    /// Keeps the cursor inside a `cols` x `rows` grid, for cursors saved
+    /// before a resize.
+    fn clamped(mut self, cols: usize, rows: usize) -> Self {
+        self.row = self.row.min(rows - 1);
+        self.col = self.col.min(cols - 1);
+        self.wrap_pending = false;
+        self
+    }
+}
+
+/// Screen model fed with raw PTY output: a visible grid plus scrollback.
+pub struct TerminalScreen {
+    parser: Parser,
+    grid: Grid,
+}
+
+impl TerminalScreen {
+    pub fn new(cols: u16, rows: u16, scrollback_max: usize) -> Self {
+        Self {
+            parser: Parser::new(),
+            grid: Grid::new(cols.max(1) as usize, rows.max(1) as usize, scrollback_max),
+        }
+    }
+
+    pub fn process(&mut self, bytes: &[u8]) {
+        self.parser.advance(&mut self.grid, bytes);
+    }
+
+    pub fn resize(&mut self, cols: u16, rows: u16) {
+        self.grid.resize(cols.max(1) as usize, rows.max(1) as usize);
+    }
+
+    /// Keeps rows that fall off the end of scrollback so the caller can
+    /// move them elsewhere with [`TerminalScreen::take_evicted`].
+    pub fn keep_evicted(&mut self, enabled: bool) {
+        self.grid.evicted = enabled.then(Vec::new);
+    }
+
+    /// Rows dropped from scrollback since the last call, oldest first.
+    pub fn take_evicted(&mut self) -> Vec<Line> {
+        self.grid
+            .evicted
+            .as_mut()
+            .map(mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Text currently visible on screen, one row per line.
+    pub fn visible_text(&self) -> String {
+        let mut rows: Vec<String> = self.grid.active().iter().map(Line::text).collect();
+        while rows.last().is_some_and(|row| row.is_empty()) {
+            rows.pop();
+        }
+        rows.join("\n")
+    }
+
+    /// The last `limit` logical lines across scrollback and screen, with
+    /// soft-wrapped rows joined back together.
+    pub fn last_lines(&self, limit: usize) -> String {
+        let mut lines = self.lines();
+        let skip = lines.len().saturating_sub(limit);
+        lines.drain(..skip);
+        lines.join("\n")
+    }
+
+    /// Every row from the oldest scrollback line to the last non-blank row on
+    /// screen.
+    pub fn rows(&self) -> impl DoubleEndedIterator<Item = &Line> {
+        let active = self.grid.active();
+        let used = active
+            .iter()
+            .rposition(|line| !line.is_blank())
+            .map_or(0, |idx| idx + 1)
+            .max(if self.grid.alternate.is_some() {
+                0
+            } else {
+                self.grid.cursor.row
+            });
+        self.grid
+            .scrollback_rows()
+            .chain(active[..used.min(active.len())].iter())
+    }
+
+    /// Escape sequences that repaint the visible screen on a terminal that
+    /// missed some of the output: modes, scroll region, every row with its
+    /// styling, then the cursor and its pen.
+    pub fn redraw(&self) -> String {
+        let grid = &self.grid;
+        // CAN abandons any sequence the receiver was halfway through.
+        let mut out = String::from("\x18\x1b[0m");
+        let alternate = if grid.alternate.is_some() { 'h' } else { 'l' };
+        let autowrap = if grid.autowrap { 'h' } else { 'l' };
+        let _ = write!(
+            out,
+            "\x1b[?1049{alternate}\x1b[?7{autowrap}\x1b[{};{}r\x1b[H\x1b[2J",
+            grid.scroll_top + 1,
+            grid.scroll_bottom + 1
+        );
+
+        for (row, line) in grid.active().iter().enumerate() {
+            let len = line.content_len();
+            if len == 0 {
+                continue;
+            }
+            let _ = write!(out, "\x1b[{};1H", row + 1);
+            let mut current = Style::default();
+            for cell in line.cells[..len]
+                .iter()
+                .filter(|cell| !cell.is_wide_spacer())
+            {
+                if cell.style != current {
+                    let _ = write!(out, "\x1b[0;{}m", export::sgr(&cell.style));
+                    current = cell.style;
+                }
+                out.push(cell.ch);
+            }
+            out.push_str("\x1b[0m");
+        }
+
+        let cursor = &grid.cursor;
+        let _ = write!(
+            out,
+            "\x1b[{};{}H\x1b[0;{}m",
+            cursor.row + 1,
+            cursor.col + 1,
+            export::sgr(&cursor.style)
+        );
+        out
+    }
+
+    /// Logical lines across scrollback and screen, oldest first.
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        self.scan(|_, line| {
+            lines.push(line.to_string());
+            true
+        });
+        lines
+    }
+
+    /// Number of lines [`TerminalScreen::lines`] returns, counted without
+    /// building them.
+    pub fn line_count(&self) -> usize {
+        let mut count = 0;
+        let mut open = false;
+        for row in self.rows() {
+            open = row.wrapped;
+            if !row.wrapped {
+                count += 1;
+            }
+        }
+        count + usize::from(open)
+    }
+
+    /// Visits logical lines oldest first until `visit` returns `false`,
+    /// building only one line at a time.
+    pub fn scan<F>(&self, mut visit: F)
+    where
+        F: FnMut(usize, &str) -> bool,
+    {
+        let mut index = 0;
+        let mut current = String::new();
+        for row in self.rows() {
+            current.push_str(&row.raw_text());
+            if !row.wrapped {
+                current.truncate(current.trim_end().len());
+                if !visit(index, &current) {
+                    return;
+                }
+                index += 1;
+                current.clear();
+            }
+        }
+        if !current.is_empty() {
+            current.truncate(current.trim_end().len());
+            visit(index, &current);
+        }
+    }
+
+    /// Logical lines in `range`, indexed as in [`TerminalScreen::lines`].
+    /// Rows are walked back from the newest, so a page near the bottom only
+    /// builds the lines it returns.
+    pub fn line_range(&self, range: Range<usize>) -> Vec<String> {
+        let mut lines = Vec::with_capacity(range.len());
+        let mut index = self.line_count();
+        let mut rows = self.rows().rev().peekable();
+        while index > range.start {
+            let Some(last) = rows.next() else {
+                break;
+            };
+            index -= 1;
+            // The row ending the line, then the rows that wrapped into it.
+            let mut parts = vec![last];
+            while let Some(row) = rows.next_if(|row| row.wrapped) {
+                parts.push(row);
+            }
+            if index < range.end {
+                let mut line: String = parts.iter().rev().map(|row| row.raw_text()).collect();
+                line.truncate(line.trim_end().len());
+                lines.push(line);
+            }
+        }
+        lines.reverse();
+        lines
+    }
+}
+
+struct Grid {
+    cols: usize,
+    rows: usize,
+    lines: Vec<Line>,
+    scrollback: VecDeque<Line>,
+    scrollback_max: usize,
+    /// Rows pushed out of scrollback, collected only when requested.
+    evicted: Option<Vec<Line>>,
+    cursor: Cursor,
+    saved_cursor: Option<Cursor>,
+    /// Primary screen lines and cursor while the alternate screen is active.
+    alternate: Option<(Vec<Line>, Cursor)>,
+    scroll_top: usize,
+    scroll_bottom: usize,
+    autowrap: bool,
+    last_printed: Option<char>,
+}
+
+impl Grid {
+    fn new(cols: usize, rows: usize, scrollback_max: usize) -> Self {
+        Self {
+            cols,
+            rows,
+            lines: vec![Line::new(cols, Style::default()); rows],
+            scrollback: VecDeque::new(),
+            scrollback_max,
+            evicted: None,
+            cursor: Cursor::default(),
+            saved_cursor: None,
+            alternate: None,
+            scroll_top: 0,
+            scroll_bottom: rows - 1,
+            autowrap: true,
+            last_printed: None,
+        }
+    }
+
+    fn active(&self) -> &[Line] {
+        &self.lines
+    }
+
+    fn scrollback_rows(&self) -> impl DoubleEndedIterator<Item = &Line> {
+        self.scrollback.iter()
+    }
+
+    fn resize(&mut self, cols: usize, rows: usize) {
+        if cols == self.cols && rows == self.rows {
+            return;
+        }
+
+        for line in &mut self.lines {
+            line.resize(cols);
+        }
+        if let Some((primary, _)) = &mut self.alternate {
+            for line in primary.iter_mut() {
+                line.resize(cols);
+            }
+        }
+
+        // Shrinking pushes rows above the cursor into scrollback, like xterm.
+        while self.lines.len() > rows {
+            if self.cursor.row > 0 && self.lines.len() - 1 > self.cursor.row {
+                self.lines.pop();
+            } else {
+                let line = self.lines.remove(0);
+                self.push_scrollback(line);
+                self.cursor.row = self.cursor.row.saturating_sub(1);
+            }
+        }
+        while self.lines.len() < rows {
+            self.lines.push(Line::new(cols, Style::default()));
+        }
+        if let Some((primary, cursor)) = &mut self.alternate {
+            primary.resize(rows, Line::new(cols, Style::default()));
+            *cursor = cursor.clamped(cols, rows);
+        }
+
+        self.cols = cols;
+        self.rows = rows;
+        self.scroll_top = 0;
+        self.scroll_bottom = rows - 1;
+        self.cursor = self.cursor.clamped(cols, rows);
+        self.saved_cursor = self.saved_cursor.map(|saved| saved.clamped(cols, rows));
+    }
+
+    fn push_scrollback(&mut self, line: Line) {
+        if self.alternate.is_some() {
+            return;
+        }
+        self.scrollback.push_back(line);
+        while self.scrollback.len() > self.scrollback_max {
+            let Some(line) = self.scrollback.pop_front() else {
+                break;
+            };
+            if let Some(evicted) = &mut self.evicted {
+                evicted.push(line);
+            }
+        }
+    }
+
+    fn blank_line(&self) -> Line {
+        Line::new(self.cols, self.cursor.style)
+    }
+
+    fn scroll_up(&mut self, count: usize) {
+        for _ in 0..count.min(self.scroll_bottom + 1 - self.scroll_top) {
+            let line = self.lines.remove(self.scroll_top);
+            if self.scroll_top == 0 {
+                self.push_scrollback(line);
+            }
+            let blank = self.blank_line();
+            self.lines.insert(self.scroll_bottom, blank);
+        }
+    }
+
+    fn scroll_down(&mut self, count: usize) {
+        for _ in 0..count.min(self.scroll_bottom + 1 - self.scroll_top) {
+            self.lines.remove(self.scroll_bottom);
+            let blank = self.blank_line();
+            self.lines.insert(self.scroll_top, blank);
+        }
+    }
+
+    fn linefeed(&mut self) {
+        self.cursor.wrap_pending = false;
+        if self.cursor.row == self.scroll_bottom {
+            self.scroll_up(1);
+        } else if self.cursor.row + 1 < self.rows {
+            self.cursor.row += 1;
+        }
+    }
+
+    fn reverse_index(&mut self) {
+        self.cursor.wrap_pending = false;
+        if self.cursor.row == self.scroll_top {
+            self.scroll_down(1);
+        } else {
+            self.cursor.row = self.cursor.row.saturating_sub(1);
+        }
+    }
+
+    fn carriage_return(&mut self) {
+        self.cursor.col = 0;
+        self.cursor.wrap_pending = false;
+    }
+
+    fn put_char(&mut self, ch: char) {
+        let width = ch.width().unwrap_or(0);
+        if width == 0 {
+            return;
+        }
+
+        if self.cursor.wrap_pending || self.cursor.col + width > self.cols {
+            if self.autowrap {
+                self.lines[self.cursor.row].wrapped = true;
+                self.carriage_return();
+                self.linefeed();
+            } else {
+                self.cursor.col = self.cols.saturating_sub(width);
+            }
+        }
+
+        let style = self.cursor.style;
+        let row = &mut self.lines[self.cursor.row];
+        row.cells[self.cursor.col] = Cell { ch, style };
+        if width == 2 && self.cursor.col + 1 < self.cols {
+            row.cells[self.cursor.col + 1] = Cell { ch: '\0', style };
+        }
+
+        self.cursor.col += width;
+        if self.cursor.col >= self.cols {
+            self.cursor.col = self.cols - 1;
+            self.cursor.wrap_pending = true;
+        }
+        self.last_printed = Some(ch);
+    }
+
+    fn move_to(&mut self, row: usize, col: usize) {
+        self.cursor.row = row.min(self.rows - 1);
+        self.cursor.col = col.min(self.cols - 1);
+        self.cursor.wrap_pending = false;
+    }
+
+    fn erase_cells(&mut self, row: usize, from: usize, to: usize) {
+        let blank = Cell::blank(self.cursor.style);
+        let cells = &mut self.lines[row].cells;
+        let to = to.min(cells.len());
+        for cell in &mut cells[from.min(to)..to] {
+            *cell = blank;
+        }
+    }
+
+    fn erase_in_display(&mut self, mode: u16) {
+        let (row, col) = (self.cursor.row, self.cursor.col);
+        match mode {
+            0 => {
+                self.erase_cells(row, col, self.cols);
+                for row in row + 1..self.rows {
+                    self.lines[row] = self.blank_line();
+                }
+            }
+            1 => {
+                for row in 0..row {
+                    self.lines[row] = self.blank_line();
+                }
+                self.erase_cells(row, 0, col + 1);
+            }
+            2 => {
+                for row in 0..self.rows {
+                    self.lines[row] = self.blank_line();
+                }
+            }
+            3 => self.scrollback.clear(),
+            _ => {}
+        }
+    }
+
+    fn erase_in_line(&mut self, mode: u16) {
+        let (row, col) = (self.cursor.row, self.cursor.col);
+        match mode {
+            0 => self.erase_cells(row, col, self.cols),
+            1 => self.erase_cells(row, 0, col + 1),
+            2 => self.erase_cells(row, 0, self.cols),
+            _ => {}
+        }
+        if mode != 1 {
+            self.lines[row].wrapped = false;
+        }
+    }
+
+    fn insert_lines(&mut self, count: usize) {
+        if !(self.scroll_top..=self.scroll_bottom).contains(&self.cursor.row) {
+            return;
+        }
+        for _ in 0..count.min(self.scroll_bottom + 1 - self.cursor.row) {
+            self.lines.remove(self.scroll_bottom);
+            let blank = self.blank_line();
+            self.lines.insert(self.cursor.row, blank);
+        }
+        self.cursor.col = 0;
+    }
+
+    fn delete_lines(&mut self, count: usize) {
+        if !(self.scroll_top..=self.scroll_bottom).contains(&self.cursor.row) {
+            return;
+        }
+        for _ in 0..count.min(self.scroll_bottom + 1 - self.cursor.row) {
+            self.lines.remove(self.cursor.row);
+            let blank = self.blank_line();
+            self.lines.insert(self.scroll_bottom, blank);
+        }
+        self.cursor.col = 0;
+    }
+
+    fn insert_chars(&mut self, count: usize) {
+        let blank = Cell::blank(self.cursor.style);
+        let col = self.cursor.col;
+        let cells = &mut self.lines[self.cursor.row].cells;
+        for _ in 0..count.min(cells.len() - col) {
+            cells.pop();
+            cells.insert(col, blank);
+        }
+    }
+
+    fn delete_chars(&mut self, count: usize) {
+        let blank = Cell::blank(self.cursor.style);
+        let col = self.cursor.col;
+        let cells = &mut self.lines[self.cursor.row].cells;
+        for _ in 0..count.min(cells.len() - col) {
+            cells.remove(col);
+            cells.push(blank);
+        }
+    }
+
+    /// Moves back to a saved cursor, which may predate the current size.
+    fn restore_cursor(&mut self, cursor: Cursor) {
+        self.cursor = cursor.clamped(self.cols, self.rows);
+    }
+
+    fn set_alternate_screen(&mut self, enabled: bool) {
+        match (enabled, self.alternate.is_some()) {
+            (true, false) => {
+                let blank = vec![Line::new(self.cols, Style::default()); self.rows];
+                let primary = mem::replace(&mut self.lines, blank);
+                self.alternate = Some((primary, self.cursor));
+            }
+            (false, true) => {
+                if let Some((primary, cursor)) = self.alternate.take() {
+                    self.lines = primary;
+                    self.restore_cursor(cursor);
+                }
+            }
+            _ => {}
+        }
+        self.scroll_top = 0;
+        self.scroll_bottom = self.rows - 1;
+    }
+
+    fn set_private_mode(&mut self, mode: u16, enabled: bool) {
+        match mode {
+            7 => self.autowrap = enabled,
+            47 | 1047 => self.set_alternate_screen(enabled),
+            1049 => {
+                if enabled {
+                    self.saved_cursor = Some(self.cursor);
+                    self.set_alternate_screen(true);
+                } else {
+                    self.set_alternate_screen(false);
+                    if let Some(saved) = self.saved_cursor {
+                        self.restore_cursor(saved);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn reset(&mut self) {
+        let evicted = self.evicted.take();
+        *self = Grid::new(self.cols, self.rows, self.scrollback_max);
+        self.evicted = evicted;
+    }
+
+    fn select_graphic_rendition(&mut self, params: &Params) {
+        let params: Vec<&[u16]> = params.iter().collect();
+        if params.is_empty() {
+            self.cursor.style = Style::default();
+            return;
+        }
+
+        let style = &mut self.cursor.style;
+        let mut idx = 0;
+        while idx < params.len() {
+            let param = params[idx];
+            match param[0] {
+                0 => *style = Style::default(),
+                1 => style.bold = true,
+                2 => style.dim = true,
+                3 => style.italic = true,
+                4 => style.underline = true,
+                7 => style.inverse = true,
+                9 => style.strikethrough = true,
+                22 => {
+                    style.bold = false;
+                    style.dim = false;
+                }
+                23 => style.italic = false,
+                24 => style.underline = false,
+                27 => style.inverse = false,
+                29 => style.strikethrough = false,
+                code @ 30..=37 => style.fg = Color::Indexed((code - 30) as u8),
+                39 => style.fg = Color::Default,
+                code @ 40..=47 => style.bg = Color::Indexed((code - 40) as u8),
+                49 => style.bg = Color::Default,
+                code @ 90..=97 => style.fg = Color::Indexed((code - 90 + 8) as u8),
+                code @ 100..=107 => style.bg = Color::Indexed((code - 100 + 8) as u8),
+                code @ (38 | 48) => {
+                    let (color, consumed) = extended_color(&params[idx..]);
+                    if let Some(color) = color {
+                        if code == 38 {
+                            style.fg = color;
+                        } else {
+                            style.bg = color;
+                        }
+                    }
+                    idx += consumed;
+                    continue;
+                }
+                _ => {}
+            }
+            idx += 1;
+        }
+    }
+}
+
+/// Parses a `38`/`48` colour starting at `params[0]`, in either the
+/// `38;5;n` / `38;2;r;g;b` or the colon sub-parameter form. Returns the colour
+/// and how many parameters it spanned.
+fn extended_color(params: &[&[u16]]) -> (Option<Color>, usize) {
+    let head = params[0];
+    if head.len() > 1 {
+        let color = match head[1] {
+            5 => head.get(2).map(|&n| Color::Indexed(n as u8)),
+            // Both `38:2:r:g:b` and `38:2:colorspace:r:g:b` occur in the wild.
+            2 if head.len() >= 6 => Some(Color::Rgb(head[3] as u8, head[4] as u8, head[5] as u8)),
+            2 if head.len() == 5 => Some(Color::Rgb(head[2] as u8, head[3] as u8, head[4] as u8)),
+            _ => None,
+        };
+        return (color, 1);
+    }
+
+    let value = |idx: usize| params.get(idx).map(|param| param[0]);
+    match value(1) {
+        Some(5) => (value(2).map(|n| Color::Indexed(n as u8)), 3),
+        Some(2) => match (value(2), value(3), value(4)) {
+            (Some(r), Some(g), Some(b)) => (Some(Color::Rgb(r as u8, g as u8, b as u8)), 5),
+            _ => (None, params.len()),
+        },
+        _ => (None, 2.min(params.len())),
+    }
+}
+
+/// First value of parameter `idx`, treating 0 and absent as `default`.
+fn param(params: &Params, idx: usize, default: u16) -> u16 {
+    params
+        .iter()
+        .nth(idx)
+        .map(|param| param[0])
+        .filter(|&value| value != 0)
+        .unwrap_or(default)
+}
+
+impl Perform for Grid {
+    fn print(&mut self, ch: char) {
+        self.put_char(ch);
+    }
+
+    fn execute(&mut self, byte: u8) {
+        match byte {
+            b'\n' | 0x0b | 0x0c => self.linefeed(),
+            b'\r' => self.carriage_return(),
+            0x08 => {
+                self.cursor.col = self.cursor.col.saturating_sub(1);
+                self.cursor.wrap_pending = false;
+            }
+            b'\t' => {
+                let next = (self.cursor.col / TAB_WIDTH + 1) * TAB_WIDTH;
+                self.cursor.col = next.min(self.cols - 1);
+            }
+            _ => {}
+        }
+    }
+
+    fn csi_dispatch(&mut self, params: &Params, intermediates: &[u8], ignore: bool, action: char) {
+        if ignore {
+            return;
+        }
+
+        if intermediates == b"?" {
+            let enabled = match action {
+                'h' => true,
+                'l' => false,
+                _ => return,
+            };
+            for mode in params.iter() {
+                self.set_private_mode(mode[0], enabled);
+            }
+            return;
+        }
+        if !intermediates.is_empty() {
+            return;
+        }
+
+        let n = param(params, 0, 1) as usize;
+        let (row, col) = (self.cursor.row, self.cursor.col);
+        match action {
+            '@' => self.insert_chars(n),
+            'A' => self.move_to(row.saturating_sub(n).max(self.scroll_top.min(row)), col),
+            'B' | 'e' => self.move_to((row + n).min(self.scroll_bottom.max(row)), col),
+            'C' | 'a' => self.move_to(row, col + n),
+            'D' => self.move_to(row, col.saturating_sub(n)),
+            'E' => self.move_to(row + n, 0),
+            'F' => self.move_to(row.saturating_sub(n), 0),
+            'G' | '`' => self.move_to(row, n - 1),
+            'H' | 'f' => {
+                let target_row = param(params, 0, 1) as usize - 1;
+                let target_col = param(params, 1, 1) as usize - 1;
+                self.move_to(target_row, target_col);
+            }
+            'J' => self.erase_in_display(params.iter().next().map_or(0, |param| param[0])),
+            'K' => self.erase_in_line(params.iter().next().map_or(0, |param| param[0])),
+            'L' => self.insert_lines(n),
+            'M' => self.delete_lines(n),
+            'P' => self.delete_chars(n),
+            'S' => self.scroll_up(n),
+            'T' => self.scroll_down(n),
+            'X' => self.erase_cells(row, col, col + n),
+            'b' => {
+                if let Some(ch) = self.last_printed {
+                    for _ in 0..n.min(self.cols * self.rows) {
+                        self.put_char(ch);
+                    }
+                }
+            }
+            'd' => self.move_to(n - 1, col),
+            'm' => self.select_graphic_rendition(params),
+            'r' => {
+                let top = param(params, 0, 1) as usize - 1;
+                let bottom = (param(params, 1, self.rows as u16) as usize).min(self.rows) - 1;
+                if top < bottom {
+                    self.scroll_top = top;
+                    self.scroll_bottom = bottom;
+                    self.move_to(0, 0);
+                }
+            }
+            's' => self.saved_cursor = Some(self.cursor),
+            'u' => {
+                if let Some(saved) = self.saved_cursor {
+                    self.restore_cursor(saved);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn esc_dispatch(&mut self, intermediates: &[u8], ignore: bool, byte: u8) {
+        if ignore || !intermediates.is_empty() {
+            return;
+        }
+        match byte {
+            b'7' => self.saved_cursor = Some(self.cursor),
+            b'8' => {
+                if let Some(saved) = self.saved_cursor {
+                    self.restore_cursor(saved);
+                }
+            }
+            b'D' => self.linefeed(),
+            b'E' => {
+                self.carriage_return();
+                self.linefeed();
+            }
+            b'M' => self.reverse_index(),
+            b'c' => self.reset(),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Screen with the cursor moved to 1-based `row` of 40.
+    fn screen_at_row(row: u16) -> TerminalScreen {
+        let mut screen = TerminalScreen::new(80, 40, 100);
+        screen.process(format!("\x1b[{row};1H").as_bytes());
+        screen
+    }
+
+    #[test]
+    fn leaving_alternate_screen_after_shrink_keeps_cursor_on_screen() {
+        let mut screen = screen_at_row(35);
+        screen.process(b"\x1b[?1049h");
+        screen.resize(80, 20);
+        screen.process(b"\x1b[?1049lprompt$ ");
+        assert!(screen.grid.cursor.row < 20);
+        assert!(screen.visible_text().ends_with("prompt$"));
+    }
+
+    #[test]
+    fn switching_screens_with_47_after_shrink_keeps_cursor_on_screen() {
+        let mut screen = screen_at_row(38);
+        screen.process(b"\x1b[?47h");
+        screen.resize(80, 10);
+        screen.process(b"\x1b[?47lx");
+        assert!(screen.grid.cursor.row < 10);
+    }
+
+    #[test]
+    fn restoring_cursor_with_esc_8_after_shrink_keeps_cursor_on_screen() {
+        let mut screen = screen_at_row(36);
+        screen.process(b"\x1b7");
+        screen.resize(80, 20);
+        screen.process(b"\x1b8x");
+        assert!(screen.grid.cursor.row < 20);
+        assert!(screen.visible_text().ends_with('x'));
+    }
+
+    #[test]
+    fn restoring_cursor_with_csi_u_after_narrowing_keeps_cursor_on_screen() {
+        let mut screen = screen_at_row(36);
+        screen.process(b"\x1b[36;70H\x1b[s");
+        screen.resize(40, 20);
+        screen.process(b"\x1b[ux");
+        assert!(screen.grid.cursor.row < 20);
+        assert!(screen.grid.cursor.col < 40);
+    }
+
+    #[test]
+    fn redraw_reproduces_screen_on_a_fresh_terminal() {
+        let mut screen = TerminalScreen::new(20, 5, 100);
+        screen.process("one\r\n\x1b[1;31mred\x1b[0m 界\r\n\x1b[2;18r\x1b[4;3H\x1b[4m".as_bytes());
+
+        let mut copy = TerminalScreen::new(20, 5, 100);
+        copy.process(b"stale \x1b[");
+        copy.process(screen.redraw().as_bytes());
+
+        assert_eq!(copy.grid.lines, screen.grid.lines);
+        assert_eq!(copy.grid.scroll_bottom, screen.grid.scroll_bottom);
+        assert_eq!(copy.grid.cursor.row, 3);
+        assert_eq!(copy.grid.cursor.col, 2);
+        assert_eq!(copy.grid.cursor.style, screen.grid.cursor.style);
+    }
+
+    #[test]
+    fn line_range_matches_lines_including_wrapped_rows() {
+        let mut screen = TerminalScreen::new(10, 4, 20);
+        for n in 0..12 {
+            let line = "ab".repeat(n % 4 * 4);
+            screen.process(format!("{n}:{line}\r\n").as_bytes());
+        }
+        screen.process(b"tail that wraps");
+
+        let lines = screen.lines();
+        assert_eq!(screen.line_count(), lines.len());
+        for start in 0..=lines.len() {
+            for end in start..=lines.len() {
+                assert_eq!(screen.line_range(start..end), lines[start..end]);
+            }
+        }
+        assert_eq!(lines.last().unwrap(), "tail that wraps");
+    }
+}
@@ -0,0 +1,247 @@
+use std::{
+    collections::VecDeque,
+    fs::{self, File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// Rows kept in memory when a session does not configure its own limit.
+pub const DEFAULT_SCROLLBACK_LINES: usize = 2000;
+/// Upper bound for the in-memory scrollback of a single session.
+pub const SCROLLBACK_LINES_MAX: usize = 100_000;
+/// Upper bound for the on-disk scrollback of a single session.
+pub const SCROLLBACK_DISK_MB_MAX: u64 = 1024;
+/// Read size used when scanning the whole ring.
+const SCAN_BLOCK_BYTES: u64 = 1024 * 1024;
+
+/// Per-session scrollback limits.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScrollbackConfig {
+    /// Rows kept in memory beyond the visible screen.
+    #[serde(default)]
+    pub lines: Option<usize>,
+    /// Size of the on-disk ring that receives rows evicted from memory.
+    /// Disabled when unset or zero.
+    #[serde(default)]
+    pub disk_mb: Option<u64>,
+}
+
+impl ScrollbackConfig {
+    pub fn memory_lines(&self) -> usize {
+        self.lines
+            .unwrap_or(DEFAULT_SCROLLBACK_LINES)
+            .min(SCROLLBACK_LINES_MAX)
+    }
+
+    pub fn disk_bytes(&self) -> Option<u64> {
+        self.disk_mb
+            .filter(|&mb| mb > 0)
+            .map(|mb| mb.min(SCROLLBACK_DISK_MB_MAX) * 1024 * 1024)
+    }
+}
+
+/// Fixed-size ring file of text lines. Once full, the oldest lines are
+/// overwritten. The file is removed when the ring is dropped.
+pub struct DiskScrollback {
+    file: File,
+    path: PathBuf,
+    capacity: u64,
+    /// Logical (ever-increasing) start offset of each stored line.
+    starts: VecDeque<u64>,
+    /// Logical offset one past the last byte written.
+    end: u64,
+    /// Soft-wrapped rows waiting for the row that ends their line.
+    pending: String,
+}
+
+impl DiskScrollback {
+    pub fn create(path: &Path, capacity: u64) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create {}", parent.display()))?;
+        }
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .with_context(|| format!("failed to create {}", path.display()))?;
+
+        Ok(Self {
+            file,
+            path: path.to_path_buf(),
+            capacity: capacity.max(1),
+            starts: VecDeque::new(),
+            end: 0,
+            pending: String::new(),
+        })
+    }
+
+    /// Number of complete lines currently stored.
+    pub fn len(&self) -> usize {
+        self.starts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.starts.is_empty()
+    }
+
+    /// Appends one screen row, joining soft-wrapped rows into a single line.
+    pub fn push_row(&mut self, text: &str, wrapped: bool) -> Result<()> {
+        self.pending.push_str(text);
+        if wrapped {
+            return Ok(());
+        }
+        let line = std::mem::take(&mut self.pending);
+        self.push_line(line.trim_end())
+    }
+
+    fn push_line(&mut self, text: &str) -> Result<()> {
+        // A line longer than the whole ring keeps only its tail.
+        let max = (self.capacity - 1) as usize;
+        let mut start = text.len().saturating_sub(max);
+        while !text.is_char_boundary(start) {
+            start += 1;
+        }
+        let mut record = Vec::with_capacity(text.len() - start + 1);
+        record.extend_from_slice(&text.as_bytes()[start..]);
+        record.push(b'\n');
+
+        self.write_at(self.end, &record)?;
+        self.starts.push_back(self.end);
+        self.end += record.len() as u64;
+        while self
+            .starts
+            .front()
+            .is_some_and(|&start| self.end - start > self.capacity)
+        {
+            self.starts.pop_front();
+        }
+        Ok(())
+    }
+
+    /// Reads the line at `index`, oldest first.
+    pub fn line(&self, index: usize) -> Result<String> {
+        let start = *self
+            .starts
+            .get(index)
+            .with_context(|| format!("scrollback line {index} out of range"))?;
+        let stop = self.starts.get(index + 1).copied().unwrap_or(self.end);
+        let mut bytes = vec![0; (stop - start - 1) as usize];
+        self.read_at(start, &mut bytes)?;
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    /// Visits stored lines oldest first until `visit` returns `false`, reading
+    /// the ring in large blocks rather than line by line.
+    pub fn scan<F>(&self, mut visit: F) -> Result<()>
+    where
+        F: FnMut(usize, &str) -> bool,
+    {
+        let mut block = Vec::new();
+        let mut block_start = 0;
+        for (index, &start) in self.starts.iter().enumerate() {
+            let stop = self.starts.get(index + 1).copied().unwrap_or(self.end);
+            if start < block_start || stop > block_start + block.len() as u64 {
+                let len = (self.end - start).min(SCAN_BLOCK_BYTES.max(stop - start));
+                block.resize(len as usize, 0);
+                self.read_at(start, &mut block)?;
+                block_start = start;
+            }
+            let bytes = &block[(start - block_start) as usize..(stop - 1 - block_start) as usize];
+            if !visit(index, &String::from_utf8_lossy(bytes)) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_at(&self, offset: u64, bytes: &[u8]) -> Result<()> {
+        let physical = offset % self.capacity;
+        let first = bytes.len().min((self.capacity - physical) as usize);
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(physical))?;
+        file.write_all(&bytes[..first])?;
+        if first < bytes.len() {
+            file.seek(SeekFrom::Start(0))?;
+            file.write_all(&bytes[first..])?;
+        }
+        Ok(())
+    }
+
+    fn read_at(&self, offset: u64, bytes: &mut [u8]) -> Result<()> {
+        let physical = offset % self.capacity;
+        let first = bytes.len().min((self.capacity - physical) as usize);
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(physical))?;
+        file.read_exact(&mut bytes[..first])?;
+        if first < bytes.len() {
+            file.seek(SeekFrom::Start(0))?;
+            file.read_exact(&mut bytes[first..])?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for DiskScrollback {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ring(name: &str, capacity: u64) -> DiskScrollback {
+        let path = std::env::temp_dir().join(format!(
+            "termalime-scrollback-{name}-{}.ring",
+            std::process::id()
+        ));
+        DiskScrollback::create(&path, capacity).unwrap()
+    }
+
+    fn stored(ring: &DiskScrollback) -> Vec<String> {
+        (0..ring.len())
+            .map(|index| ring.line(index).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn ring_keeps_the_newest_lines_in_order_after_wrapping() {
+        let mut ring = ring("wrap", 100);
+        for n in 0..50 {
+            ring.push_row(&format!("line {n}"), false).unwrap();
+        }
+
+        let lines = stored(&ring);
+        assert!(lines.len() < 50);
+        let first = 50 - lines.len();
+        let expected: Vec<String> = (first..50).map(|n| format!("line {n}")).collect();
+        assert_eq!(lines, expected);
+
+        let mut scanned = Vec::new();
+        ring.scan(|index, text| {
+            scanned.push((index, text.to_string()));
+            true
+        })
+        .unwrap();
+        assert_eq!(scanned.len(), lines.len());
+        assert!(scanned.iter().all(|(index, text)| *text == lines[*index]));
+    }
+
+    #[test]
+    fn wrapped_rows_are_joined_and_long_lines_keep_their_tail() {
+        let mut ring = ring("join", 16);
+        ring.push_row("abc", true).unwrap();
+        ring.push_row("def  ", false).unwrap();
+        assert_eq!(stored(&ring), ["abcdef"]);
+
+        ring.push_row(&"x".repeat(40), false).unwrap();
+        assert_eq!(stored(&ring), ["x".repeat(15)]);
+    }
+}
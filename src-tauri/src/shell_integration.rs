@@ -0,0 +1,644 @@
+use std::{
+    collections::VecDeque,
+    env, fs,
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    pty::{default_shell, SpawnOptions},
+    unix_millis,
+};
+
+const BASH_SCRIPT: &str = include_str!("../shell-integration/termalime.bash");
+const ZSH_SCRIPT: &str = include_str!("../shell-integration/termalime.zsh");
+const FISH_SCRIPT: &str = include_str!("../shell-integration/termalime.fish");
+
+/// Number of finished commands kept per session.
+const SHELL_COMMANDS_MAX: usize = 500;
+/// Longest OSC payload we buffer before giving up on the sequence.
+const OSC_PAYLOAD_MAX: usize = 8 * 1024;
+/// Output kept per finished command, measured from the end.
+pub const COMMAND_OUTPUT_MAX: usize = 64 * 1024;
+/// Longest echoed command line captured when the shell does not report one.
+const ECHO_CAPTURE_MAX: usize = 4 * 1024;
+
+const ESC: u8 = 0x1b;
+const BEL: u8 = 0x07;
+
+/// Shells we ship integration scripts for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShellKind {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl ShellKind {
+    pub fn detect(program: &str) -> Option<Self> {
+        let name = Path::new(program).file_name()?.to_str()?;
+        match name.trim_start_matches('-') {
+            "bash" => Some(Self::Bash),
+            "zsh" => Some(Self::Zsh),
+            "fish" => Some(Self::Fish),
+            _ => None,
+        }
+    }
+
+    pub fn script(self) -> &'static str {
+        match self {
+            Self::Bash => BASH_SCRIPT,
+            Self::Zsh => ZSH_SCRIPT,
+            Self::Fish => FISH_SCRIPT,
+        }
+    }
+}
+
+/// Rewrites `options` so the shell loads the integration script on startup,
+/// after the user's own rc files. Shells we do not recognise, and shells
+/// started with explicit arguments, are left untouched.
+pub fn inject(options: &mut SpawnOptions, scripts_dir: &Path) -> Result<()> {
+    let program = options.program.clone().unwrap_or_else(default_shell);
+    let Some(kind) = ShellKind::detect(&program) else {
+        return Ok(());
+    };
+    if !options.args.is_empty() {
+        return Ok(());
+    }
+
+    fs::create_dir_all(scripts_dir)
+        .with_context(|| format!("failed to create {}", scripts_dir.display()))?;
+
+    match kind {
+        ShellKind::Bash => {
+            let script = write_script(scripts_dir, "termalime.bash", BASH_SCRIPT)?;
+            let rcfile = write_script(
+                scripts_dir,
+                "bashrc",
+                &format!(
+                    "[ -f ~/.bashrc ] && . ~/.bashrc\n. {}\n",
+                    shell_quote(&script)
+                ),
+            )?;
+            options.args = vec!["--rcfile".into(), rcfile, "-i".into()];
+        }
+        ShellKind::Zsh => {
+            let script = write_script(scripts_dir, "termalime.zsh", ZSH_SCRIPT)?;
+            let zdotdir = scripts_dir.join("zsh");
+            fs::create_dir_all(&zdotdir)
+                .with_context(|| format!("failed to create {}", zdotdir.display()))?;
+            write_script(
+                &zdotdir,
+                ".zshenv",
+                &format!(
+                    "if [[ -n \"$TERMALIME_USER_ZDOTDIR\" ]]; then ZDOTDIR=\"$TERMALIME_USER_ZDOTDIR\"; else unset ZDOTDIR; fi\n\
+                     unset TERMALIME_USER_ZDOTDIR\n\
+                     [[ -f \"${{ZDOTDIR:-$HOME}}/.zshenv\" ]] && source \"${{ZDOTDIR:-$HOME}}/.zshenv\"\n\
+                     [[ -o interactive ]] && source {}\n",
+                    shell_quote(&script)
+                ),
+            )?;
+            let user_zdotdir = options
+                .env
+                .get("ZDOTDIR")
+                .cloned()
+                .or_else(|| env::var("ZDOTDIR").ok())
+                .unwrap_or_default();
+            options
+                .env
+                .insert("TERMALIME_USER_ZDOTDIR".into(), user_zdotdir);
+            options
+                .env
+                .insert("ZDOTDIR".into(), zdotdir.to_string_lossy().into_owned());
+        }
+        ShellKind::Fish => {
+            let script = write_script(scripts_dir, "termalime.fish", FISH_SCRIPT)?;
+            options.args = vec![
+                "--init-command".into(),
+                format!("source {}", shell_quote(&script)),
+            ];
+        }
+    }
+
+    options.program = Some(program);
+    Ok(())
+}
+
+fn write_script(dir: &Path, name: &str, contents: &str) -> Result<String> {
+    let path = dir.join(name);
+    fs::write(&path, contents).with_context(|| format!("failed to write {}", path.display()))?;
+    Ok(path.to_string_lossy().into_owned())
+}
+
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// Shell integration marks recognised in the PTY output stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ShellEvent {
+    /// OSC 133;A — the prompt is about to be drawn.
+    PromptStart,
+    /// OSC 133;B — the prompt ended and command input begins.
+    InputStart,
+    /// OSC 133;C — the command was submitted and its output follows.
+    OutputStart { command_line: Option<String> },
+    /// OSC 133;D — the command finished.
+    CommandFinished { exit_code: Option<i32> },
+    /// OSC 7 — the shell reported its working directory.
+    Cwd(String),
+}
+
+#[derive(Default, Clone, Copy)]
+enum ScanState {
+    #[default]
+    Ground,
+    Escape,
+    Osc,
+    OscEscape,
+}
+
+/// A recognised mark and the stream offsets of the sequence that carried it.
+struct ScannedEvent {
+    start: u64,
+    end: u64,
+    event: ShellEvent,
+}
+
+/// Incremental OSC sequence scanner that tolerates sequences split across reads.
+#[derive(Default, Clone)]
+struct OscScanner {
+    state: ScanState,
+    payload: Vec<u8>,
+    overflowed: bool,
+    /// Stream offset of the ESC that opened the current sequence.
+    sequence_start: u64,
+}
+
+impl OscScanner {
+    /// Feeds `bytes`, which start at stream offset `base`.
+    fn feed(&mut self, bytes: &[u8], base: u64) -> Vec<ScannedEvent> {
+        let mut events = Vec::new();
+        for (idx, &byte) in bytes.iter().enumerate() {
+            let offset = base + idx as u64;
+            self.state = match (self.state, byte) {
+                (ScanState::Ground | ScanState::Escape, ESC) => {
+                    self.sequence_start = offset;
+                    ScanState::Escape
+                }
+                (ScanState::Ground, _) => ScanState::Ground,
+                (ScanState::Escape, b']') => self.begin_osc(),
+                (ScanState::Escape, _) => ScanState::Ground,
+                (ScanState::Osc, BEL) => {
+                    events.extend(self.finish(offset + 1));
+                    ScanState::Ground
+                }
+                (ScanState::Osc, ESC) => ScanState::OscEscape,
+                (ScanState::Osc, _) => {
+                    if self.payload.len() < OSC_PAYLOAD_MAX {
+                        self.payload.push(byte);
+                    } else {
+                        self.overflowed = true;
+                    }
+                    ScanState::Osc
+                }
+                (ScanState::OscEscape, b'\\') => {
+                    events.extend(self.finish(offset + 1));
+                    ScanState::Ground
+                }
+                // Any other byte aborts the OSC; the ESC may open a new one.
+                (ScanState::OscEscape, b']') => {
+                    self.sequence_start = offset - 1;
+                    self.begin_osc()
+                }
+                (ScanState::OscEscape, _) => ScanState::Ground,
+            };
+        }
+        events
+    }
+
+    /// Stream offset of a sequence that is still open, if any.
+    fn pending_start(&self) -> Option<u64> {
+        match self.state {
+            ScanState::Ground => None,
+            _ => Some(self.sequence_start),
+        }
+    }
+
+    fn begin_osc(&mut self) -> ScanState {
+        self.payload.clear();
+        self.overflowed = false;
+        ScanState::Osc
+    }
+
+    fn finish(&mut self, end: u64) -> Option<ScannedEvent> {
+        if self.overflowed {
+            return None;
+        }
+        let payload = String::from_utf8_lossy(&self.payload).into_owned();
+        parse_osc(&payload).map(|event| ScannedEvent {
+            start: self.sequence_start,
+            end,
+            event,
+        })
+    }
+}
+
+fn parse_osc(payload: &str) -> Option<ShellEvent> {
+    if let Some(url) = payload.strip_prefix("7;") {
+        return parse_file_url(url).map(ShellEvent::Cwd);
+    }
+
+    let mark = payload.strip_prefix("133;")?;
+    let (kind, params) = mark.split_once(';').unwrap_or((mark, ""));
+    match kind {
+        "A" => Some(ShellEvent::PromptStart),
+        "B" => Some(ShellEvent::InputStart),
+        "C" => {
+            let command_line = params
+                .split_once("cmdline=")
+                .map(|(_, line)| line.trim().to_string())
+                .filter(|line| !line.is_empty());
+            Some(ShellEvent::OutputStart { command_line })
+        }
+        "D" => Some(ShellEvent::CommandFinished {
+            exit_code: params
+                .split(';')
+                .next()
+                .and_then(|code| code.trim().parse().ok()),
+        }),
+        _ => None,
+    }
+}
+
+/// Extracts the path from an OSC 7 `file://host/path` URL.
+fn parse_file_url(url: &str) -> Option<String> {
+    let rest = url.strip_prefix("file://")?;
+    let path = &rest[rest.find('/')?..];
+    Some(percent_decode(path))
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        if bytes[idx] == b'%' && idx + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[idx + 1..idx + 3]).unwrap_or("");
+            if let Ok(byte) = u8::from_str_radix(hex, 16) {
+                out.push(byte);
+                idx += 3;
+                continue;
+            }
+        }
+        out.push(bytes[idx]);
+        idx += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// A command delimited by shell integration marks.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShellCommand {
+    pub id: u64,
+    pub command: String,
+    pub cwd: Option<String>,
+    /// Offsets into the session's output stream covering the command output.
+    pub output_start: u64,
+    pub output_end: Option<u64>,
+    pub exit_code: Option<i32>,
+    /// Milliseconds since the Unix epoch.
+    pub started_at: u64,
+    pub duration_ms: Option<u64>,
+}
+
+/// A finished command together with the output it produced.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedCommand {
+    #[serde(flatten)]
+    pub command: ShellCommand,
+    /// Raw output, keeping only the last [`COMMAND_OUTPUT_MAX`] bytes.
+    pub output: String,
+    pub output_truncated: bool,
+}
+
+#[derive(Default, Clone, Copy, PartialEq, Eq)]
+enum TrackerPhase {
+    #[default]
+    Idle,
+    Prompt,
+    Input,
+    Running,
+}
+
+/// Per-session record of shell integration state and finished commands.
+#[derive(Default, Clone)]
+pub struct ShellTracker {
+    scanner: OscScanner,
+    phase: TrackerPhase,
+    /// Total bytes of output seen so far.
+    offset: u64,
+    /// Bytes of a sequence still open at the end of the previous chunk.
+    held: Vec<u8>,
+    echo: Vec<u8>,
+    output: Vec<u8>,
+    output_truncated: bool,
+    cwd: Option<String>,
+    running: Option<ShellCommand>,
+    commands: VecDeque<RecordedCommand>,
+    next_id: u64,
+}
+
+impl ShellTracker {
+    /// Consumes a chunk of output, returning commands that finished within it.
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<RecordedCommand> {
+        let base = self.offset;
+        let mut finished = Vec::new();
+        let mut cursor = 0;
+        let events = self.scanner.feed(chunk, base);
+
+        // Bytes held back from the previous chunk are either still part of an
+        // open sequence, the start of this chunk's first mark, or ordinary output.
+        let pending = self.scanner.pending_start();
+        let held = std::mem::take(&mut self.held);
+        if pending.is_some_and(|start| start < base) {
+            self.held = held;
+        } else if events.first().is_none_or(|first| first.start >= base) {
+            self.capture(&held);
+        }
+
+        for ScannedEvent { start, end, event } in events {
+            let start_in_chunk = start.saturating_sub(base) as usize;
+            self.capture(&chunk[cursor..start_in_chunk.max(cursor)]);
+            cursor = (end - base) as usize;
+
+            match event {
+                ShellEvent::PromptStart => {
+                    // A prompt without a finish mark means the shell lost track
+                    // (e.g. Ctrl-C in bash); close the command without a status.
+                    if let Some(command) = self.finish_running(start, None) {
+                        finished.push(command);
+                    }
+                    self.phase = TrackerPhase::Prompt;
+                }
+                ShellEvent::InputStart => {
+                    self.echo.clear();
+                    self.phase = TrackerPhase::Input;
+                }
+                ShellEvent::OutputStart { command_line } => {
+                    let command = command_line.unwrap_or_else(|| echoed_command(&self.echo));
+                    self.echo.clear();
+                    self.output.clear();
+                    self.output_truncated = false;
+                    self.next_id += 1;
+                    self.running = Some(ShellCommand {
+                        id: self.next_id,
+                        command,
+                        cwd: self.cwd.clone(),
+                        output_start: end,
+                        output_end: None,
+                        exit_code: None,
+                        started_at: unix_millis(),
+                        duration_ms: None,
+                    });
+                    self.phase = TrackerPhase::Running;
+                }
+                ShellEvent::CommandFinished { exit_code } => {
+                    if let Some(command) = self.finish_running(start, exit_code) {
+                        finished.push(command);
+                    }
+                    self.phase = TrackerPhase::Idle;
+                }
+                ShellEvent::Cwd(cwd) => self.cwd = Some(cwd),
+            }
+        }
+
+        let tail_end = match pending {
+            Some(start) if start >= base => (start - base) as usize,
+            Some(_) => cursor,
+            None => chunk.len(),
+        };
+        self.capture(&chunk[cursor..tail_end.max(cursor)]);
+        if pending.is_some() {
+            let open = &chunk[tail_end.max(cursor)..];
+            let room = OSC_PAYLOAD_MAX.saturating_sub(self.held.len());
+            self.held.extend_from_slice(&open[..open.len().min(room)]);
+        }
+
+        self.offset += chunk.len() as u64;
+        finished
+    }
+
+    /// Most recent working directory reported through OSC 7.
+    pub fn cwd(&self) -> Option<&str> {
+        self.cwd.as_deref()
+    }
+
+    /// Finished commands, oldest first.
+    pub fn commands(&self) -> impl DoubleEndedIterator<Item = &RecordedCommand> {
+        self.commands.iter()
+    }
+
+    pub fn command(&self, id: u64) -> Option<&RecordedCommand> {
+        self.commands.iter().find(|record| record.command.id == id)
+    }
+
+    pub fn running(&self) -> Option<&ShellCommand> {
+        self.running.as_ref()
+    }
+
+    fn finish_running(&mut self, output_end: u64, exit_code: Option<i32>) -> Option<RecordedCommand> {
+        let mut command = self.running.take()?;
+        command.output_end = Some(output_end.max(command.output_start));
+        command.exit_code = exit_code;
+        command.duration_ms = Some(unix_millis().saturating_sub(command.started_at));
+        let record = RecordedCommand {
+            command,
+            output: String::from_utf8_lossy(&std::mem::take(&mut self.output)).into_owned(),
+            output_truncated: std::mem::take(&mut self.output_truncated),
+        };
+        self.commands.push_back(record.clone());
+        while self.commands.len() > SHELL_COMMANDS_MAX {
+            self.commands.pop_front();
+        }
+        Some(record)
+    }
+
+    fn capture(&mut self, bytes: &[u8]) {
+        match self.phase {
+            TrackerPhase::Input => {
+                let room = ECHO_CAPTURE_MAX.saturating_sub(self.echo.len());
+                self.echo.extend_from_slice(&bytes[..bytes.len().min(room)]);
+            }
+            TrackerPhase::Running => {
+                self.output.extend_from_slice(bytes);
+                if self.output.len() > COMMAND_OUTPUT_MAX {
+                    let mut excess = self.output.len() - COMMAND_OUTPUT_MAX;
+                    // Keep the tail aligned to a UTF-8 character boundary.
+                    while excess < self.output.len() && (self.output[excess] & 0xC0) == 0x80 {
+                        excess += 1;
+                    }
+                    self.output.drain(..excess);
+                    self.output_truncated = true;
+                }
+            }
+            TrackerPhase::Idle | TrackerPhase::Prompt => {}
+        }
+    }
+}
+
+/// Best-effort recovery of a command line from its terminal echo, used when
+/// the integration script does not report `cmdline=`.
+fn echoed_command(echo: &[u8]) -> String {
+    let text = String::from_utf8_lossy(echo);
+    let mut line = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\u{1b}' => {
+                // Skip CSI/OSC sequences emitted by line editors.
+                match chars.peek() {
+                    Some('[') => {
+                        chars.next();
+                        for next in chars.by_ref() {
+                            if ('@'..='~').contains(&next) {
+                                break;
+                            }
+                        }
+                    }
+                    Some(']') => {
+                        for next in chars.by_ref() {
+                            if next == '\u{7}' {
+                                break;
+                            }
+                        }
+                    }
+                    _ => {
+                        chars.next();
+                    }
+                }
+            }
+            '\u{8}' | '\u{7f}' => {
+                line.pop();
+            }
+            '\r' | '\n' => {}
+            ch if ch.is_control() => {}
+            ch => line.push(ch),
+        }
+    }
+    line.trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A prompt, a typed `ls`, its output, and the finish mark. C ends with
+    /// BEL and D with ST so both terminators are covered.
+    const CYCLE: &[u8] =
+        b"\x1b]133;A\x07$ \x1b]133;B\x07ls\x1b]133;C\x07file\r\n\x1b]133;D;2\x1b\\";
+
+    fn feed_all(tracker: &mut ShellTracker, chunks: &[&[u8]]) -> Vec<RecordedCommand> {
+        chunks
+            .iter()
+            .flat_map(|chunk| tracker.feed(chunk))
+            .collect()
+    }
+
+    #[test]
+    fn command_cycle_is_recognised_at_every_split() {
+        let output_start = CYCLE.windows(4).position(|w| w == b"file").unwrap() as u64;
+        let output_end = output_start + b"file\r\n".len() as u64;
+
+        for split in 0..=CYCLE.len() {
+            let mut tracker = ShellTracker::default();
+            let finished = feed_all(&mut tracker, &[&CYCLE[..split], &CYCLE[split..]]);
+
+            assert_eq!(finished.len(), 1, "split at {split}");
+            let record = &finished[0];
+            assert_eq!(record.command.command, "ls", "split at {split}");
+            assert_eq!(record.output, "file\r\n", "split at {split}");
+            assert_eq!(record.command.exit_code, Some(2), "split at {split}");
+            let command = &record.command;
+            assert_eq!(command.output_start, output_start, "split at {split}");
+            assert_eq!(command.output_end, Some(output_end), "split at {split}");
+            assert!(record.command.duration_ms.is_some());
+            assert!(tracker.running().is_none());
+        }
+    }
+
+    #[test]
+    fn command_cycle_is_recognised_one_byte_at_a_time() {
+        let mut tracker = ShellTracker::default();
+        let chunks: Vec<&[u8]> = CYCLE.chunks(1).collect();
+        let finished = feed_all(&mut tracker, &chunks);
+
+        assert_eq!(finished.len(), 1);
+        assert_eq!(finished[0].command.command, "ls");
+        assert_eq!(finished[0].output, "file\r\n");
+    }
+
+    #[test]
+    fn osc_7_reports_percent_decoded_cwd() {
+        let mut tracker = ShellTracker::default();
+        tracker.feed(b"\x1b]7;file://host.local/home/me/My%20Docs/%C3%A9t%C3%A9\x1b\\");
+        assert_eq!(tracker.cwd(), Some("/home/me/My Docs/été"));
+
+        let finished = tracker.feed(b"\x1b]133;C;cmdline=make\x07\x1b]133;D;0\x07");
+        assert_eq!(finished[0].command.command, "make");
+        assert_eq!(
+            finished[0].command.cwd.as_deref(),
+            Some("/home/me/My Docs/été")
+        );
+    }
+
+    #[test]
+    fn osc_7_without_path_is_ignored() {
+        let mut tracker = ShellTracker::default();
+        tracker.feed(b"\x1b]7;file://host\x07\x1b]7;http://host/tmp\x07");
+        assert_eq!(tracker.cwd(), None);
+    }
+
+    #[test]
+    fn prompt_without_finish_mark_closes_command_without_status() {
+        let mut tracker = ShellTracker::default();
+        let stream = b"\x1b]133;C;cmdline=sleep 9\x07^C\r\n\x1b]133;A\x07$ ";
+        let finished = tracker.feed(stream);
+
+        assert_eq!(finished.len(), 1);
+        let command = &finished[0].command;
+        assert_eq!(command.command, "sleep 9");
+        assert_eq!(command.exit_code, None);
+        assert_eq!(finished[0].output, "^C\r\n");
+        let prompt_start = stream.len() as u64 - b"\x1b]133;A\x07$ ".len() as u64;
+        assert_eq!(command.output_end, Some(prompt_start));
+        assert!(tracker.running().is_none());
+    }
+
+    #[test]
+    fn unterminated_osc_is_dropped_once_over_the_limit() {
+        let mut tracker = ShellTracker::default();
+        tracker.feed(b"\x1b]133;C;cmdline=");
+        let filler = vec![b'x'; 1024];
+        for _ in 0..(OSC_PAYLOAD_MAX / filler.len() + 2) {
+            assert!(tracker.feed(&filler).is_empty());
+            assert!(tracker.held.len() <= OSC_PAYLOAD_MAX);
+        }
+        assert!(tracker.feed(b"\x07").is_empty());
+        assert!(tracker.running().is_none());
+
+        // The scanner recovers for the marks that follow.
+        let finished = tracker.feed(b"\x1b]133;C;cmdline=true\x07\x1b]133;D;0\x07");
+        assert_eq!(finished.len(), 1);
+        assert_eq!(finished[0].command.command, "true");
+    }
+
+    #[test]
+    fn unknown_and_interrupted_sequences_are_not_marks() {
+        let mut tracker = ShellTracker::default();
+        tracker.feed(b"\x1b]0;title\x07\x1b]133;C\x1b[31m\x1b]133;Z\x07");
+        assert!(tracker.running().is_none());
+    }
+}
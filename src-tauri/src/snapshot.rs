@@ -0,0 +1,343 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    ops::Range,
+    path::Path,
+    sync::{Arc, Mutex, MutexGuard, RwLock},
+};
+
+use anyhow::Result;
+use regex::Regex;
+use serde::Serialize;
+
+use crate::{
+    export::{self, ExportFormat},
+    pty::PtySize,
+    screen::TerminalScreen,
+    scrollback::{DiskScrollback, ScrollbackConfig},
+    shell_integration::{RecordedCommand, ShellTracker},
+};
+
+/// Everything stored about one session's output: the emulated screen, any
+/// scrollback spilled to disk, and the commands the shell has reported.
+pub struct TerminalSnapshot {
+    screen: TerminalScreen,
+    /// Receives rows evicted from in-memory scrollback when disk scrollback
+    /// is enabled for the session.
+    spill: Option<DiskScrollback>,
+    shell: ShellTracker,
+}
+
+impl TerminalSnapshot {
+    pub fn new(
+        size: PtySize,
+        scrollback: &ScrollbackConfig,
+        spill: Option<DiskScrollback>,
+    ) -> Self {
+        let mut screen = TerminalScreen::new(size.cols, size.rows, scrollback.memory_lines());
+        screen.keep_evicted(spill.is_some());
+        Self {
+            screen,
+            spill,
+            shell: ShellTracker::default(),
+        }
+    }
+
+    /// Feeds output, returning any commands the shell reported as finished.
+    pub fn append(&mut self, chunk: &[u8]) -> Vec<RecordedCommand> {
+        self.screen.process(chunk);
+        if let Some(spill) = &mut self.spill {
+            let result = self
+                .screen
+                .take_evicted()
+                .iter()
+                .try_for_each(|row| spill.push_row(&row.raw_text(), row.wrapped));
+            if let Err(err) = result {
+                eprintln!("disabling disk scrollback: {err:#}");
+                self.spill = None;
+                self.screen.keep_evicted(false);
+            }
+        }
+        self.shell.feed(chunk)
+    }
+
+    pub fn resize(&mut self, cols: u16, rows: u16) {
+        self.screen.resize(cols, rows);
+    }
+
+    /// See [`TerminalScreen::redraw`].
+    pub fn redraw(&self) -> String {
+        self.screen.redraw()
+    }
+
+    pub fn shell(&self) -> &ShellTracker {
+        &self.shell
+    }
+
+    /// Returns up to `limit` lines ending `offset` lines above the bottom,
+    /// along with the total number of stored lines.
+    pub fn page(&self, offset: usize, limit: usize) -> Result<(Vec<String>, usize)> {
+        let total = self.total_lines();
+        let end = total.saturating_sub(offset);
+        let start = end.saturating_sub(limit);
+        Ok((self.line_range(start..end)?, total))
+    }
+
+    /// Finds up to `max_hits` matches, oldest first, each with `context`
+    /// lines on either side. Lines are searched in one pass, one at a time.
+    pub fn search(
+        &self,
+        matcher: &Regex,
+        max_hits: usize,
+        context: usize,
+    ) -> Result<TerminalSearchResults> {
+        let spilled = self.spilled_lines();
+        let total = self.total_lines();
+        let mut hits: Vec<TerminalSearchHit> = Vec::new();
+        let mut truncated = false;
+        // The lines just visited, which become the next hit's `before`.
+        let mut recent: VecDeque<String> = VecDeque::with_capacity(context);
+        let mut visit = |line: usize, text: &str| {
+            // Hits still short of `after` lines are always the newest ones.
+            for hit in hits
+                .iter_mut()
+                .rev()
+                .take_while(|hit| hit.after.len() < context)
+            {
+                hit.after.push(text.to_string());
+            }
+            if !truncated {
+                for found in matcher.find_iter(text) {
+                    if hits.len() == max_hits {
+                        truncated = true;
+                        break;
+                    }
+                    hits.push(TerminalSearchHit {
+                        line,
+                        offset: total - line - 1,
+                        start: text[..found.start()].chars().count(),
+                        end: text[..found.end()].chars().count(),
+                        text: text.to_string(),
+                        before: recent.iter().cloned().collect(),
+                        after: Vec::new(),
+                    });
+                }
+            }
+            if context > 0 {
+                if recent.len() == context {
+                    recent.pop_front();
+                }
+                recent.push_back(text.to_string());
+            }
+            !truncated || hits.last().is_some_and(|hit| hit.after.len() < context)
+        };
+
+        let mut finished = false;
+        if let Some(spill) = &self.spill {
+            spill.scan(|line, text| {
+                finished = !visit(line, text);
+                !finished
+            })?;
+        }
+        if !finished {
+            self.screen.scan(|index, text| visit(spilled + index, text));
+        }
+
+        Ok(TerminalSearchResults {
+            hits,
+            total_lines: total,
+            truncated,
+        })
+    }
+
+    /// Writes the stored output to `path` in the given format.
+    pub fn export(&self, format: ExportFormat, path: &Path) -> Result<()> {
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = std::fs::File::create(path)?;
+        let mut out = std::io::BufWriter::new(file);
+        export::write(&mut out, format, self.spill.as_ref(), &self.screen)
+    }
+
+    fn spilled_lines(&self) -> usize {
+        self.spill.as_ref().map_or(0, DiskScrollback::len)
+    }
+
+    fn total_lines(&self) -> usize {
+        self.spilled_lines() + self.screen.line_count()
+    }
+
+    /// Lines in `range`, indexed from the oldest line on disk through the
+    /// lines on screen.
+    fn line_range(&self, range: Range<usize>) -> Result<Vec<String>> {
+        let spilled = self.spilled_lines();
+        let mut lines = Vec::with_capacity(range.len());
+        if let Some(spill) = &self.spill {
+            for index in range.start..range.end.min(spilled) {
+                lines.push(spill.line(index)?);
+            }
+        }
+        let screen_range = range.start.max(spilled) - spilled..range.end.max(spilled) - spilled;
+        lines.extend(self.screen.line_range(screen_range));
+        Ok(lines)
+    }
+}
+
+#[derive(Serialize)]
+pub struct TerminalSearchHit {
+    /// Index of the matching line, counted from the oldest stored line.
+    pub line: usize,
+    /// `offset` for `get_terminal_context` that ends its page at this line.
+    pub offset: usize,
+    /// Character columns of the match within `text`.
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+    pub before: Vec<String>,
+    pub after: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct TerminalSearchResults {
+    pub hits: Vec<TerminalSearchHit>,
+    pub total_lines: usize,
+    /// More matches exist beyond `max_hits`.
+    pub truncated: bool,
+}
+
+/// One session's snapshot behind its own lock. Readers feeding output and
+/// commands querying it only contend with each other, never with other
+/// sessions.
+#[derive(Clone)]
+pub struct SnapshotHandle(Arc<Mutex<TerminalSnapshot>>);
+
+impl SnapshotHandle {
+    pub fn lock(&self) -> MutexGuard<'_, TerminalSnapshot> {
+        self.0.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// Snapshots keyed by session id. The map lock is only taken to look a
+/// session up, so it is never held while output is parsed or searched.
+#[derive(Default)]
+pub struct SnapshotStore {
+    sessions: RwLock<HashMap<String, SnapshotHandle>>,
+}
+
+impl SnapshotStore {
+    /// Stores `snapshot` for `session_id`, replacing any previous one, and
+    /// returns a handle the session's reader can keep.
+    pub fn insert(&self, session_id: &str, snapshot: TerminalSnapshot) -> SnapshotHandle {
+        let handle = SnapshotHandle(Arc::new(Mutex::new(snapshot)));
+        self.sessions
+            .write()
+            .unwrap_or_else(|err| err.into_inner())
+            .insert(session_id.to_string(), handle.clone());
+        handle
+    }
+
+    pub fn get(&self, session_id: &str) -> Option<SnapshotHandle> {
+        self.sessions
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .get(session_id)
+            .cloned()
+    }
+
+    pub fn remove(&self, session_id: &str) {
+        self.sessions
+            .write()
+            .unwrap_or_else(|err| err.into_inner())
+            .remove(session_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 20x4 session keeping 5 rows in memory and spilling the rest to a
+    /// 200-byte ring, fed `count` numbered lines.
+    fn snapshot_with_lines(name: &str, count: usize) -> TerminalSnapshot {
+        let size = PtySize {
+            cols: 20,
+            rows: 4,
+            pixel_width: 0,
+            pixel_height: 0,
+        };
+        let config = ScrollbackConfig {
+            lines: Some(5),
+            disk_mb: None,
+        };
+        let path = std::env::temp_dir().join(format!(
+            "termalime-snapshot-{name}-{}.ring",
+            std::process::id()
+        ));
+        let spill = DiskScrollback::create(&path, 200).unwrap();
+        let mut snapshot = TerminalSnapshot::new(size, &config, Some(spill));
+        for n in 0..count {
+            snapshot.append(format!("line {n}\r\n").as_bytes());
+        }
+        snapshot
+    }
+
+    /// The number at the end of each `line N`.
+    fn numbers(lines: &[String]) -> Vec<usize> {
+        lines
+            .iter()
+            .map(|line| line.trim_start_matches("line ").parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn paging_past_ring_capacity_returns_the_newest_lines_in_order() {
+        let snapshot = snapshot_with_lines("page", 100);
+        let (lines, total) = snapshot.page(0, 10).unwrap();
+        assert!(snapshot.spilled_lines() > 0);
+        assert_eq!(numbers(&lines), (90..100).collect::<Vec<_>>());
+
+        // The whole store, across the disk and screen boundary.
+        let (lines, _) = snapshot.page(0, total).unwrap();
+        let numbers = numbers(&lines);
+        let first = numbers[0];
+        assert!(first > 0);
+        assert_eq!(numbers, (first..100).collect::<Vec<_>>());
+
+        let (lines, _) = snapshot.page(3, 2).unwrap();
+        assert_eq!(lines, ["line 95", "line 96"]);
+    }
+
+    #[test]
+    fn search_context_spans_disk_and_screen() {
+        let snapshot = snapshot_with_lines("search", 100);
+        let spilled = snapshot.spilled_lines();
+        let total = snapshot.total_lines();
+        let last_on_disk = snapshot.page(total - spilled, 1).unwrap().0.remove(0);
+        let matcher = Regex::new(&format!("^{last_on_disk}$")).unwrap();
+
+        let results = snapshot.search(&matcher, 10, 2).unwrap();
+        assert_eq!(results.hits.len(), 1);
+        let hit = &results.hits[0];
+        assert_eq!(hit.line, spilled - 1);
+        assert_eq!(hit.offset, total - spilled);
+        let n: usize = last_on_disk.trim_start_matches("line ").parse().unwrap();
+        assert_eq!(numbers(&hit.before), [n - 2, n - 1]);
+        assert_eq!(numbers(&hit.after), [n + 1, n + 2]);
+    }
+
+    #[test]
+    fn search_stops_at_max_hits_but_fills_context() {
+        let snapshot = snapshot_with_lines("truncate", 100);
+        let matcher = Regex::new("line 9").unwrap();
+
+        let results = snapshot.search(&matcher, 3, 1).unwrap();
+        assert!(results.truncated);
+        let lines: Vec<&str> = results.hits.iter().map(|hit| hit.text.as_str()).collect();
+        assert_eq!(lines, ["line 90", "line 91", "line 92"]);
+        assert_eq!(results.hits[2].after, ["line 93"]);
+        assert_eq!(results.hits[0].before, ["line 89"]);
+    }
+}
@@ -0,0 +1,105 @@
+/// Incremental UTF-8 decoder for byte streams read in arbitrary chunks.
+///
+/// A character split across two chunks is held back until the rest of it
+/// arrives instead of decoding as U+FFFD on both sides. Bytes that can never
+/// form valid UTF-8 are still replaced with U+FFFD.
+#[derive(Debug, Default)]
+pub struct Utf8Decoder {
+    pending: Vec<u8>,
+}
+
+impl Utf8Decoder {
+    pub fn decode(&mut self, bytes: &[u8]) -> String {
+        let mut input = std::mem::take(&mut self.pending);
+        input.extend_from_slice(bytes);
+
+        let mut text = String::with_capacity(input.len());
+        let mut rest = input.as_slice();
+        loop {
+            match std::str::from_utf8(rest) {
+                Ok(valid) => {
+                    text.push_str(valid);
+                    break;
+                }
+                Err(err) => {
+                    let (valid, after) = rest.split_at(err.valid_up_to());
+                    text.push_str(std::str::from_utf8(valid).unwrap_or_default());
+                    match err.error_len() {
+                        Some(len) => {
+                            text.push(char::REPLACEMENT_CHARACTER);
+                            rest = &after[len..];
+                        }
+                        None => {
+                            // Only a truncated sequence at the very end gets here.
+                            self.pending.extend_from_slice(after);
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        text
+    }
+
+    /// Flushes held-back bytes once the stream has ended.
+    pub fn finish(&mut self) -> String {
+        let pending = std::mem::take(&mut self.pending);
+        String::from_utf8_lossy(&pending).into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Decodes `chunks` in order, then flushes.
+    fn decode_all(chunks: &[&[u8]]) -> String {
+        let mut decoder = Utf8Decoder::default();
+        let mut text: String = chunks.iter().map(|chunk| decoder.decode(chunk)).collect();
+        text.push_str(&decoder.finish());
+        text
+    }
+
+    #[test]
+    fn scalar_split_at_any_offset_is_held_back_until_whole() {
+        for text in ["aé!", "a€!", "a😀!"] {
+            let bytes = text.as_bytes();
+            for split in 0..=bytes.len() {
+                let mut decoder = Utf8Decoder::default();
+                let first = decoder.decode(&bytes[..split]);
+                let second = decoder.decode(&bytes[split..]);
+                assert!(!first.contains(char::REPLACEMENT_CHARACTER));
+                assert_eq!(first + &second, text, "{text:?} split at {split}");
+                assert_eq!(decoder.finish(), "");
+            }
+        }
+    }
+
+    #[test]
+    fn four_byte_scalar_survives_being_fed_one_byte_at_a_time() {
+        let bytes = "😀".as_bytes();
+        let chunks: Vec<&[u8]> = bytes.chunks(1).collect();
+        let mut decoder = Utf8Decoder::default();
+        for chunk in &chunks[..3] {
+            assert_eq!(decoder.decode(chunk), "");
+        }
+        assert_eq!(decoder.decode(chunks[3]), "😀");
+    }
+
+    #[test]
+    fn invalid_bytes_become_replacement_characters_without_losing_neighbours() {
+        assert_eq!(decode_all(&[b"ab\xffcd"]), "ab\u{fffd}cd");
+        assert_eq!(decode_all(&[b"ab\xff", b"\xfecd"]), "ab\u{fffd}\u{fffd}cd");
+        // A lead byte whose continuation never comes.
+        assert_eq!(decode_all(&[b"x\xe2\x82", b"y\xc3\xa9"]), "x\u{fffd}yé");
+        assert_eq!(decode_all(&[b"\xc3", b"\xc3\xa9"]), "\u{fffd}é");
+    }
+
+    #[test]
+    fn finish_flushes_a_truncated_scalar() {
+        let mut decoder = Utf8Decoder::default();
+        assert_eq!(decoder.decode(b"ok\xf0\x9f"), "ok");
+        assert_eq!(decoder.finish(), "\u{fffd}");
+        assert_eq!(decoder.finish(), "");
+    }
+}
@@ -27,9 +27,23 @@ type ChatMessage = {
 };
 
 type OllamaChunkPayload = {
+  request_id: string;
+  conversation_id: string;
   content?: string;
   done: boolean;
   error?: string;
+  cancelled?: boolean;
+};
+
+type ConversationSummary = {
+  id: string;
+};
+
+type StoredConversation = {
+  id: string;
+  model?: string | null;
+  updated_at: number;
+  messages: { role: ChatRole | "system"; content: string; created_at: number }[];
 };
 
 type TerminalContextPayload = {
@@ -82,6 +96,8 @@ const Chatbot = ({ sessionId }: ChatbotProps) => {
   const [checkingOllama, setCheckingOllama] = useState(false);
   const responseIdRef = useRef<string | null>(null);
   const responseBufferRef = useRef<string>("");
+  const conversationIdRef = useRef<string>(createId());
+  const requestIdRef = useRef<string | null>(null);
   const bottomRef = useRef<HTMLDivElement>(null);
   const { settings } = useSettings();
 
@@ -189,6 +205,9 @@ const Chatbot = ({ sessionId }: ChatbotProps) => {
 
   const handleAssistantChunk = useCallback(
     (payload: OllamaChunkPayload) => {
+      if (payload.request_id !== requestIdRef.current) {
+        return;
+      }
       const activeResponseId = responseIdRef.current;
 
       if (payload.error) {
@@ -235,6 +254,14 @@ const Chatbot = ({ sessionId }: ChatbotProps) => {
       }
 
       if (payload.done) {
+        if (payload.cancelled && activeResponseId) {
+          setMessages((prev) =>
+            prev.map((message) =>
+              message.id === activeResponseId ? { ...message, pending: false } : message,
+            ),
+          );
+        }
+        requestIdRef.current = null;
         responseIdRef.current = null;
         responseBufferRef.current = "";
         setIsStreaming(false);
@@ -243,6 +270,41 @@ const Chatbot = ({ sessionId }: ChatbotProps) => {
     [],
   );
 
+  // Pick up the most recent conversation so reopening the panel continues it
+  useEffect(() => {
+    let cancelled = false;
+
+    const restore = async () => {
+      const [latest] = await invoke<ConversationSummary[]>("list_conversations");
+      if (!latest || cancelled) return;
+
+      const conversation = await invoke<StoredConversation>("load_conversation", {
+        conversationId: latest.id,
+      });
+      // A prompt sent while this was loading already started a new conversation
+      if (cancelled || requestIdRef.current) return;
+
+      conversationIdRef.current = conversation.id;
+      setMessages(
+        conversation.messages
+          .filter((message) => message.role !== "system")
+          .map((message) => ({
+            id: createId(),
+            role: message.role as ChatRole,
+            content: message.content,
+            model: message.role === "assistant" ? conversation.model ?? undefined : undefined,
+            timestamp: message.created_at || conversation.updated_at,
+          })),
+      );
+    };
+
+    restore().catch((error) => console.error(error));
+
+    return () => {
+      cancelled = true;
+    };
+  }, []);
+
   // Auto-retry connection on startup with exponential backoff
   useEffect(() => {
     let cancelled = false;
@@ -329,6 +391,8 @@ const Chatbot = ({ sessionId }: ChatbotProps) => {
       timestamp,
     };
 
+    const requestId = createId();
+    requestIdRef.current = requestId;
     responseIdRef.current = assistantMessage.id;
     responseBufferRef.current = "";
     setMessages((prev) => [...prev, userMessage, assistantMessage]);
@@ -340,8 +404,8 @@ const Chatbot = ({ sessionId }: ChatbotProps) => {
     if (settings.includeTerminalContext && sessionId) {
       try {
         const context = await invoke<TerminalContextPayload>("get_terminal_context", {
-          session_id: sessionId,
-          max_lines: 250,
+          sessionId,
+          maxLines: 250,
         });
         const trimmedContext = context.last_lines?.trim();
         if (trimmedContext) {
@@ -359,6 +423,9 @@ const Chatbot = ({ sessionId }: ChatbotProps) => {
     const requestPayload: Record<string, unknown> = {
       prompt: trimmed,
       model,
+      conversation_id: conversationIdRef.current,
+      request_id: requestId,
+      persona: settings.persona,
     };
 
     if (systemPrompt) {
@@ -382,6 +449,7 @@ const Chatbot = ({ sessionId }: ChatbotProps) => {
       console.error(error);
       setIsStreaming(false);
       setOllamaOnline(false);
+      requestIdRef.current = null;
       responseIdRef.current = null;
       setChatError(
         typeof error === "string"
@@ -412,6 +480,14 @@ const Chatbot = ({ sessionId }: ChatbotProps) => {
     settings,
   ]);
 
+  const cancelPrompt = useCallback(() => {
+    const requestId = requestIdRef.current;
+    if (!requestId) {
+      return;
+    }
+    invoke("cancel_ollama", { requestId }).catch((error) => console.error(error));
+  }, []);
+
   const handleSubmit = (event: FormEvent) => {
     event.preventDefault();
     void sendPrompt();
@@ -591,18 +667,20 @@ const Chatbot = ({ sessionId }: ChatbotProps) => {
             onKeyDown={handleKeyDown}
             disabled={ollamaOnline === false}
           />
-          <button type="submit" disabled={sendDisabled}>
-            {isStreaming ? (
+          {isStreaming ? (
+            <button type="button" onClick={cancelPrompt} title="Stop generating">
               <span className="chat-button__content">
                 <Loader2 size={16} className="icon-spin" />
-                Streaming
+                Stop
               </span>
-            ) : (
+            </button>
+          ) : (
+            <button type="submit" disabled={sendDisabled}>
               <span className="chat-button__content">
                 <SendHorizonal size={16} />
               </span>
-            )}
-          </button>
+            </button>
+          )}
         </form>
         {chatError && <p className="chat-error">{chatError}</p>}
       </div>
@@ -1,5 +1,6 @@
 import { useCallback, useEffect, useState } from "react";
 import {
+  Activity,
   Copy,
   GitBranch,
   Monitor,
@@ -20,8 +21,15 @@ interface SystemContext {
   username: string | null;
   localIp: string | null;
   gitBranch: string | null;
+  gitDirty: boolean;
+  gitAhead: number | null;
+  gitBehind: number | null;
+  gitStashCount: number;
+  gitCommitSubject: string | null;
   cwd: string | null;
   shell: string | null;
+  foregroundProcess: string | null;
+  foregroundPid: number | null;
 }
 
 const ContextBar = ({ onSettingsClick, sessionId }: ContextBarProps) => {
@@ -30,14 +38,21 @@ const ContextBar = ({ onSettingsClick, sessionId }: ContextBarProps) => {
     username: null,
     localIp: null,
     gitBranch: null,
+    gitDirty: false,
+    gitAhead: null,
+    gitBehind: null,
+    gitStashCount: 0,
+    gitCommitSubject: null,
     cwd: null,
     shell: null,
+    foregroundProcess: null,
+    foregroundPid: null,
   });
 
   const fetchContext = useCallback(async () => {
     try {
       const data = await invoke<SystemContext>("get_system_context", {
-        session_id: sessionId,
+        sessionId,
       });
       setContext(data);
     } catch (error) {
@@ -66,9 +81,15 @@ const ContextBar = ({ onSettingsClick, sessionId }: ContextBarProps) => {
 
       <div className="context-bar__section context-bar__section--meta">
         {context.gitBranch && (
-          <div className="context-item" title="Git branch">
+          <div className="context-item" title={context.gitCommitSubject ?? "Git branch"}>
             <GitBranch size={13} />
-            <span>{context.gitBranch}</span>
+            <span>
+              {context.gitBranch}
+              {context.gitDirty && "*"}
+              {!!context.gitAhead && ` ↑${context.gitAhead}`}
+              {!!context.gitBehind && ` ↓${context.gitBehind}`}
+              {context.gitStashCount > 0 && ` ≡${context.gitStashCount}`}
+            </span>
           </div>
         )}
 
@@ -98,6 +119,16 @@ const ContextBar = ({ onSettingsClick, sessionId }: ContextBarProps) => {
             <span>{context.shell}</span>
           </div>
         )}
+
+        {context.foregroundProcess && context.foregroundProcess !== context.shell && (
+          <div
+            className="context-item"
+            title={`Foreground process (pid ${context.foregroundPid ?? "?"})`}
+          >
+            <Activity size={13} />
+            <span>{context.foregroundProcess}</span>
+          </div>
+        )}
       </div>
 
       {context.cwd && (
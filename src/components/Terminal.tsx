@@ -16,6 +16,14 @@ type TerminalStatus = "connecting" | "ready" | "error";
 type TerminalOutputPayload = {
   data: string;
   session_id: string;
+  encoding?: "utf8" | "base64";
+  bytes: number;
+};
+
+type TerminalExitPayload = {
+  session_id: string;
+  exit_code: number;
+  signal: string | null;
 };
 
 type PreflightState = {
@@ -332,7 +340,14 @@ const Terminal = ({ onSessionChange }: TerminalProps) => {
       setStatus("connecting");
       setStatusMessage("Spawning PTY…");
       try {
-    const id = await invoke<string>("spawn_pty");
+    const id = await invoke<string>("spawn_pty", {
+          request: {
+            cols: term.cols,
+            rows: term.rows,
+            flow_control: true,
+            shell_integration: true,
+          },
+        });
   sessionIdRef.current = id;
         setSessionId(id);
     onSessionChange?.(id);
@@ -357,6 +372,13 @@ const Terminal = ({ onSessionChange }: TerminalProps) => {
       if (resizeFrameRef.current) {
         cancelAnimationFrame(resizeFrameRef.current);
       }
+      const closingId = sessionIdRef.current;
+      sessionIdRef.current = null;
+      if (closingId) {
+        invoke("kill_pty", { request: { session_id: closingId } }).catch((error) =>
+          console.error("kill_pty failed", error),
+        );
+      }
       onSessionChange?.(null);
       term.dispose();
     };
@@ -376,13 +398,38 @@ const Terminal = ({ onSessionChange }: TerminalProps) => {
     }
 
     let unlisten: UnlistenFn | undefined;
+    let unlistenExit: UnlistenFn | undefined;
 
     const attach = async () => {
       unlisten = await listen<TerminalOutputPayload>("terminal-output", (event) => {
         if (event.payload.session_id !== sessionIdRef.current) {
           return;
         }
-        termRef.current?.write(event.payload.data);
+        const { data, encoding, bytes, session_id } = event.payload;
+        // Acknowledge once xterm has parsed the chunk so the backend can pace output.
+        const ack = () => {
+          invoke("ack_terminal_output", { sessionId: session_id, bytes }).catch((error) =>
+            console.error("ack_terminal_output failed", error),
+          );
+        };
+        if (encoding === "base64") {
+          termRef.current?.write(
+            Uint8Array.from(atob(data), (char) => char.charCodeAt(0)),
+            ack,
+          );
+        } else {
+          termRef.current?.write(data, ack);
+        }
+      });
+      unlistenExit = await listen<TerminalExitPayload>("terminal-exit", (event) => {
+        if (event.payload.session_id !== sessionIdRef.current) {
+          return;
+        }
+        const { exit_code, signal } = event.payload;
+        const reason = signal ? `terminated by ${signal}` : `exited with ${exit_code}`;
+        termRef.current?.writeln(`\r\n[process ${reason}]`);
+        setStatus("error");
+        setStatusMessage("Process exited");
       });
     };
 
@@ -390,6 +437,7 @@ const Terminal = ({ onSessionChange }: TerminalProps) => {
 
     return () => {
       unlisten?.();
+      unlistenExit?.();
     };
   }, [sessionId]);
 